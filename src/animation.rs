@@ -0,0 +1,120 @@
+use crate::config::Config;
+use crate::error::ViuResult;
+use crate::printer::{BlockPrinter, Printer};
+use crossterm::cursor::{MoveToPreviousLine, RestorePosition, SavePosition};
+use crossterm::execute;
+use image::{DynamicImage, Frame};
+use std::io::Write;
+use std::time::Duration;
+
+/// Play the frames of an animation, such as a decoded GIF, in place. Every frame is shown for
+/// its own delay, and the animation repeats `loop_count` times, or forever if it is None.
+/// Returns the dimensions of the last printed frame.
+///
+/// Frames are printed with half blocks over the previous one, so the animation does not scroll.
+/// Once the animation is over, the cursor is below it, or back where it started with
+/// `restore_cursor`. Ctrl-C is not handled, it ends the process as usual. Since every row is
+/// written out with its colors reset, the terminal is still left in a clean state.
+/// ## Example
+/// ```no_run
+/// use image::AnimationDecoder;
+/// use viuer::{print_frames, Config};
+/// let file = std::fs::File::open("animation.gif").expect("Could not open file.");
+/// let frames = image::gif::GifDecoder::new(file)
+///     .expect("Could not decode GIF.")
+///     .into_frames()
+///     .collect_frames()
+///     .expect("Could not decode frames.");
+/// let conf = Config {
+///     loop_count: Some(3),
+///     ..Default::default()
+/// };
+/// print_frames(&frames, &conf).expect("Animation printing failed.");
+/// ```
+pub fn print_frames(frames: &[Frame], config: &Config) -> ViuResult<(u32, u32)> {
+    let mut stdout = std::io::stdout();
+    let mut size = (0, 0);
+    let mut loops = 0;
+    // every frame returns the cursor to where the animation starts
+    let restores_cursor = config.restores_cursor();
+    if restores_cursor {
+        execute!(stdout, SavePosition)?;
+    }
+
+    let frames: Vec<(DynamicImage, Duration)> = frames
+        .iter()
+        .map(|frame| {
+            let img = DynamicImage::ImageRgba8(frame.buffer().clone());
+            (img, frame_delay(frame))
+        })
+        .collect();
+
+    while !frames.is_empty() && config.loop_count.is_none_or(|count| loops < count) {
+        for (img, delay) in &frames {
+            let frame_config = if size.1 == 0 || config.absolute_offset || restores_cursor {
+                config.clone()
+            } else {
+                // go back up to the first line of the previous frame
+                execute!(stdout, MoveToPreviousLine(size.1 as u16))?;
+                Config {
+                    y: 0,
+                    ..config.clone()
+                }
+            };
+
+            size = BlockPrinter {}.print(img, &frame_config)?;
+            if restores_cursor {
+                execute!(stdout, RestorePosition)?;
+            }
+            std::thread::sleep(*delay);
+        }
+        loops += 1;
+    }
+    stdout.flush()?;
+    Ok(size)
+}
+
+fn frame_delay(frame: &Frame) -> Duration {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    Duration::from_micros(numer as u64 * 1000 / std::cmp::max(1, denom) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, RgbaImage};
+
+    fn frame(delay_ms: u32) -> Frame {
+        Frame::from_parts(
+            RgbaImage::new(8, 4),
+            0,
+            0,
+            Delay::from_numer_denom_ms(delay_ms, 1),
+        )
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        assert_eq!(frame_delay(&frame(40)), Duration::from_millis(40));
+        let third = Frame::from_parts(
+            RgbaImage::new(1, 1),
+            0,
+            0,
+            Delay::from_numer_denom_ms(10, 3),
+        );
+        assert_eq!(frame_delay(&third), Duration::from_micros(3333));
+    }
+
+    #[test]
+    fn test_print_frames() {
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            loop_count: Some(2),
+            ..Default::default()
+        };
+        let frames = vec![frame(0), frame(1)];
+        assert_eq!(print_frames(&frames, &config).unwrap(), (8, 2));
+        assert_eq!(print_frames(&[], &config).unwrap(), (0, 0));
+    }
+}
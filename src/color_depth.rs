@@ -0,0 +1,106 @@
+use ansi_colours::{ansi256_from_rgb, rgb_from_ansi256};
+use image::{DynamicImage, GenericImageView};
+use std::collections::HashMap;
+
+// Average distance between the colors of the image and their closest colors in the 256 color
+// palette, below which the palette is considered good enough
+const MAX_ANSI256_ERROR: f32 = 4.0;
+
+/// Colors available for printing with half blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// The 256 color palette.
+    Ansi256,
+    /// 24-bit RGB colors.
+    Truecolor,
+}
+
+/// Find the lowest color depth that shows the image without a visible loss of quality, up to
+/// the `max` depth supported by the terminal.
+///
+/// Images with few colors, such as logos, pixel art or screenshots, often fit the 256 color
+/// palette and need less than half the escape codes of truecolor. The result can be used
+/// to set `truecolor` in the [Config](crate::Config).
+/// ## Example
+/// ```no_run
+/// use viuer::{recommend_color_depth, ColorDepth, Config};
+/// let img = image::open("img.png").expect("Could not open image.");
+/// let depth = recommend_color_depth(&img, ColorDepth::Truecolor);
+/// let conf = Config {
+///     truecolor: Some(depth == ColorDepth::Truecolor),
+///     ..Default::default()
+/// };
+/// viuer::print(&img, &conf).expect("Image printing failed.");
+/// ```
+pub fn recommend_color_depth(img: &DynamicImage, max: ColorDepth) -> ColorDepth {
+    if max == ColorDepth::Ansi256 || ansi256_error(img) <= MAX_ANSI256_ERROR {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Truecolor
+    }
+}
+
+// Average distance in RGB space between the visible pixels and their 256 color palette match.
+// Every distinct color is only looked up once.
+fn ansi256_error(img: &DynamicImage) -> f32 {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for (_, _, pixel) in img.pixels() {
+        if pixel[3] != 0 {
+            *counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+        }
+    }
+
+    let mut total = 0.0;
+    let mut pixels = 0;
+    for (rgb, count) in counts {
+        let (r, g, b) = rgb_from_ansi256(ansi256_from_rgb((rgb[0], rgb[1], rgb[2])));
+        let distance = |a: u8, b: u8| (a as f32 - b as f32).powi(2);
+        let error = (distance(rgb[0], r) + distance(rgb[1], g) + distance(rgb[2], b)).sqrt();
+        total += error * count as f32;
+        pixels += count;
+    }
+
+    if pixels == 0 {
+        0.0
+    } else {
+        total / pixels as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_recommend_color_depth() {
+        // colors straight from the palette
+        let flat = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 95, 135, 255])
+            }
+        }));
+        assert_eq!(ansi256_error(&flat), 0.0);
+        assert_eq!(
+            recommend_color_depth(&flat, ColorDepth::Truecolor),
+            ColorDepth::Ansi256
+        );
+
+        let gradient = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 200, 255])
+        }));
+        assert_eq!(
+            recommend_color_depth(&gradient, ColorDepth::Truecolor),
+            ColorDepth::Truecolor
+        );
+        assert_eq!(
+            recommend_color_depth(&gradient, ColorDepth::Ansi256),
+            ColorDepth::Ansi256
+        );
+
+        let empty = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        assert_eq!(ansi256_error(&empty), 0.0);
+    }
+}
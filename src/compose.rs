@@ -0,0 +1,126 @@
+use crate::error::{ViuError, ViuResult};
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+// Alpha composite the layers, from bottom to top, onto a transparent canvas that is large
+// enough to hold all of them. Every layer is anchored at the top left corner.
+// If `linear` is true, blending happens in linear light instead of sRGB.
+pub fn composite_layers(layers: &[&DynamicImage], linear: bool) -> ViuResult<DynamicImage> {
+    if layers.is_empty() {
+        return Err(ViuError::InvalidConfiguration(
+            "at least one layer is required".to_owned(),
+        ));
+    }
+
+    let width = layers.iter().map(|l| l.width()).max().unwrap_or(0);
+    let height = layers.iter().map(|l| l.height()).max().unwrap_or(0);
+
+    let mut canvas = RgbaImage::new(width, height);
+    for layer in layers {
+        if linear {
+            for (x, y, pixel) in layer.pixels() {
+                let bottom = canvas.get_pixel_mut(x, y);
+                *bottom = blend_linear(*bottom, pixel);
+            }
+        } else {
+            imageops::overlay(&mut canvas, &layer.to_rgba8(), 0, 0);
+        }
+    }
+
+    Ok(DynamicImage::ImageRgba8(canvas))
+}
+
+// Composite `top` over `bottom` (the "over" operator) after converting both to linear light.
+// The result is converted back to sRGB.
+fn blend_linear(bottom: Rgba<u8>, top: Rgba<u8>) -> Rgba<u8> {
+    let top_a = top[3] as f32 / 255.0;
+    let bottom_a = bottom[3] as f32 / 255.0;
+    let out_a = top_a + bottom_a * (1.0 - top_a);
+    if out_a == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let mut out = [0; 4];
+    for (c, out_c) in out.iter_mut().take(3).enumerate() {
+        let top_c = srgb_to_linear(top[c]);
+        let bottom_c = srgb_to_linear(bottom[c]);
+        let linear = (top_c * top_a + bottom_c * bottom_a * (1.0 - top_a)) / out_a;
+        *out_c = linear_to_srgb(linear);
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+    Rgba(out)
+}
+
+// Convert an sRGB channel to linear light in the range [0, 1]
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Convert a linear light value in the range [0, 1] back to an sRGB channel
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_composite_layers() {
+        let mut bottom = RgbaImage::new(2, 1);
+        bottom.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        bottom.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        let mut top = RgbaImage::new(1, 2);
+        top.put_pixel(0, 0, Rgba([0, 0, 255, 255]));
+        top.put_pixel(0, 1, Rgba([0, 255, 0, 255]));
+
+        let bottom = DynamicImage::ImageRgba8(bottom);
+        let top = DynamicImage::ImageRgba8(top);
+        let img = composite_layers(&[&bottom, &top], false)
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*img.get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(0, 1), Rgba([0, 255, 0, 255]));
+        assert_eq!(img.get_pixel(1, 1)[3], 0);
+    }
+
+    #[test]
+    fn test_composite_no_layers() {
+        assert!(composite_layers(&[], false).is_err());
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for c in 0..=255 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(c)), c);
+        }
+    }
+
+    #[test]
+    fn test_blend_linear() {
+        let black = Rgba([0, 0, 0, 255]);
+        let half_white = Rgba([255, 255, 255, 128]);
+        // half white over black is brighter in linear light than the 128 an sRGB blend gives
+        let blended = blend_linear(black, half_white);
+        assert_eq!(blended[3], 255);
+        assert!(blended[0] > 180);
+
+        let opaque = Rgba([10, 20, 30, 255]);
+        assert_eq!(blend_linear(black, opaque), opaque);
+        assert_eq!(blend_linear(opaque, Rgba([0, 0, 0, 0])), opaque);
+    }
+}
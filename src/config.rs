@@ -1,35 +1,194 @@
+use crate::edges::EdgeStyle;
 use crate::utils;
+use std::time::Duration;
+use termcolor::ColorChoice;
 
 /// Configuration struct to customize printing behaviour.
+#[derive(Clone)]
 pub struct Config {
     /// [resize](crate::resize) the image before printing. Defaults to true.
     pub resize: bool,
     /// Enable true transparency instead of checkerboard background.
     /// Available only for the block printer. Defaults to false.
     pub transparent: bool,
+    /// Position (x, y) in pixels added to every pixel before picking its checkerboard color.
+    /// When printing a part of an image, set it to where that part starts, so that the pattern
+    /// stays aligned with the whole image. Available only for the block printer.
+    /// Defaults to (0, 0).
+    pub checkerboard_origin: (u32, u32),
+    /// Solid color shown by transparent pixels instead of the checkerboard, for example the
+    /// background of the panel the image is embedded in. When `transparent` is set, such pixels
+    /// stay transparent, and `opacity` and `alpha_blend` fade towards this color instead.
+    /// Available only for the block printer. Defaults to None.
+    pub transparent_bg: Option<(u8, u8, u8)>,
+    /// Composite partially transparent pixels over the background behind them, instead of
+    /// showing them as opaque. Uses linear light with `linear_compositing`. With `transparent`,
+    /// the terminal's background color is not known, so it returns an error unless
+    /// `transparent_bg` is set. Available only for the block printer. Defaults to false.
+    pub alpha_blend: bool,
+    /// Pixels with an alpha of at most this much count as fully transparent, for images that
+    /// leave specks of near zero alpha in their empty areas. Available only for the block
+    /// printer. Defaults to 0.
+    pub alpha_threshold: u8,
     /// Make the x and y offset be relative to the top left terminal corner.
-    /// If false, the y offset is relative to the cursor's position.
+    /// If false, the y offset is relative to the cursor's position: the image starts y lines
+    /// below the cursor's line, so with a y of 0 it starts at the beginning of that line.
     /// Defaults to true.
     pub absolute_offset: bool,
     /// X offset. Defaults to 0.
     pub x: u16,
+    /// Center the image in the terminal's width, ignoring the x offset. Images wider than the
+    /// terminal start at its first column. Available only for the block and sextant printers.
+    /// Defaults to false.
+    pub center: bool,
     /// Y offset. Can be negative only when `absolute_offset` is `false`. Defaults to 0.
     pub y: i16,
-    /// Take a note of cursor position before printing and restore it when finished.
+    /// When a negative y offset would move above the top of the screen, move only up to the
+    /// top row instead of returning an error. Requires the terminal to report the cursor
+    /// position, otherwise the offset is used as is. Every print with a negative y asks for it,
+    /// which takes up to `query_timeout` if the terminal does not answer. After the first
+    /// unanswered query, the later ones fail right away. Defaults to true.
+    pub clamp_offsets: bool,
+    /// Take a note of cursor position before printing and restore it when finished, instead
+    /// of leaving the cursor below the image. Ignored when stdout is not a terminal.
     /// Defaults to false.
     pub restore_cursor: bool,
     /// Optional image width. Defaults to None.
     pub width: Option<u32>,
     /// Optional image height. Defaults to None.
     pub height: Option<u32>,
-    /// Use truecolor if the terminal supports it. Defaults to true.
-    pub truecolor: bool,
+    /// Use truecolor. If None, support is checked with
+    /// [detect_truecolor](crate::detect_truecolor) on every print, so changes to the
+    /// environment are respected. Defaults to None.
+    pub truecolor: Option<bool>,
+    /// Whether to print colors. If None, they are left out when `NO_COLOR` is set or stdout
+    /// is not a terminal. Without colors, [print](crate::print) falls back to plain text, while
+    /// the printers that need them to show the image return an error. Defaults to None.
+    pub color_choice: Option<ColorChoice>,
+    /// When the two pixels of a cell differ by at most this much in every channel, print
+    /// them as a single full block of their average color. Reduces the seams some fonts show
+    /// between half blocks. Available only for the block printer. Defaults to None.
+    pub merge_similar_halves: Option<u8>,
+    /// Print the image with the 24 shades of gray of the 256 color palette, even if truecolor
+    /// is available, for a stepped monochrome look. Available only for the block printer.
+    /// Defaults to false.
+    pub force_ansi_grayscale: bool,
+    /// Spread the difference between the colors of the image and the 256 color palette over
+    /// neighboring pixels with Floyd-Steinberg dithering, which hides banding in gradients. Has
+    /// no effect with truecolor. Available only for the block printer. Defaults to false.
+    pub dither: bool,
+    /// Darken the image towards its corners. The value is how much darker the corners get,
+    /// where 0.0 does nothing and 1.0 makes them black. Available only for the block printer.
+    /// Defaults to None.
+    pub vignette: Option<f32>,
+    /// Matrix multiplied with the (r, g, b) vector of every pixel, for color grading effects
+    /// such as sepia or channel swaps. Rows give the output red, green and blue, and the results
+    /// are clamped to 0-255. Available only for the block printer. Defaults to None.
+    pub color_matrix: Option<[[f32; 3]; 3]>,
+    /// Fade the whole image towards the background, from 0.0 (invisible) to 1.0 (unchanged).
+    /// The background is the checkerboard or `transparent_bg`. With `transparent`, the
+    /// terminal's background color is not known, so values below 1.0 return an error unless
+    /// `transparent_bg` is set. Available only for the block printer. Defaults to 1.0.
+    pub opacity: f32,
+    /// Desaturate the image, using the Rec. 601 luma of every pixel. Applied after the other
+    /// color adjustments. Available only for the block printer. Defaults to false.
+    pub grayscale: bool,
+    /// Invert the colors of the image, after `grayscale`. The checkerboard is inverted as well.
+    /// Available only for the block printer. Defaults to false.
+    pub invert: bool,
+    /// Detect edges in the image and draw them over it, or on their own, as described by the
+    /// [EdgeStyle]. Available only for the block printer. Defaults to None.
+    pub edge_overlay: Option<EdgeStyle>,
+    /// What to do with the bottom pixel rows of an image whose height is not a multiple of the
+    /// pixel rows in a cell. Available only for the block printer.
+    /// Defaults to [PartialRowPolicy::Pad].
+    pub partial_row_policy: PartialRowPolicy,
+    /// Characters used to draw the pixels. Available only for the block printer.
+    /// Defaults to [BlockStyle::HalfBlock].
+    pub block_style: BlockStyle,
+    /// Most terminal rows kept in memory before they are written to stdout. Larger values
+    /// mean fewer writes, which helps with slow pipes, while memory stays bounded however tall
+    /// the image is. With the `rayon` feature, the colors of the whole image are still computed
+    /// up front, so only the output is bounded. Available only for the block printer. Defaults
+    /// to None, which writes every row as soon as it is ready.
+    pub max_in_flight_rows: Option<usize>,
+    /// Terminal size (columns, rows) used when the real one cannot be detected or is reported
+    /// as 0, as in some headless environments. Defaults to (80, 24).
+    pub fallback_terminal_size: (u16, u16),
     /// Use Kitty protocol if the terminal supports it. Defaults to true.
     pub use_kitty: bool,
     /// Use iTerm protocol if the terminal supports it. Defaults to true.
     pub use_iterm: bool,
     /// Use Sixel protocol if the terminal supports it. Defaults to true.
     pub use_sixel: bool,
+    /// Print with sextant characters, which show 2x3 pixels in every cell, instead of half
+    /// blocks when no graphics protocol is used. Requires a font with the Symbols for Legacy
+    /// Computing. Defaults to false.
+    pub use_sextants: bool,
+    /// Print in monochrome with braille patterns, which show 2x4 dots in every cell, when no
+    /// graphics protocol is used. Suits line art, diagrams and QR codes. Takes precedence over
+    /// `use_sextants`. Defaults to false.
+    pub use_braille: bool,
+    /// Pixels with a luma of at least this much are drawn as raised braille dots.
+    /// Defaults to 128.
+    pub braille_threshold: u8,
+    /// Color of the braille dots, or the terminal's foreground color if None.
+    /// Defaults to None.
+    pub braille_color: Option<(u8, u8, u8)>,
+    /// Print plain text, with a character for every pixel picked by its luma, when no
+    /// graphics protocol is used. Also used when colors are disabled, see `color_choice`.
+    /// Moving the cursor to the offsets takes escape codes, so when stdout is not a terminal
+    /// only a relative y offset of at least 0 is honored, as empty lines. Defaults to false.
+    pub use_ascii: bool,
+    /// Characters used by the ASCII printer, from the darkest pixels to the brightest.
+    /// Defaults to None, which stands for `" .:-=+*#%@"`.
+    pub ascii_ramp: Option<String>,
+    /// How long to wait for the terminal to answer capability queries, such as the ones
+    /// checking for Kitty and Sixel support. Unanswered queries are treated as missing support.
+    /// Kitty and Sixel support are only checked once per process, with the timeout of the
+    /// first print that needs them. Defaults to 1 second.
+    pub query_timeout: Duration,
+    /// Alpha composite the layers given to [print_layers](crate::print_layers), and the pixels
+    /// blended with `alpha_blend`, in linear light instead of sRGB, which avoids dark fringes
+    /// around edges. Defaults to false.
+    pub linear_compositing: bool,
+    /// How many terminal cells the viewport moves with every [pan_left](crate::pan_left),
+    /// [pan_right](crate::pan_right), [pan_up](crate::pan_up) or [pan_down](crate::pan_down).
+    /// Defaults to 1.
+    pub page_scroll_step: u32,
+    /// Stacking order of the image relative to the text, used only by the Kitty printer.
+    /// Negative values place the image behind the text. Defaults to None.
+    pub z_index: Option<i32>,
+    /// Channel difference a pixel must exceed to be shown by [print_diff](crate::print_diff).
+    /// Defaults to 0.
+    pub diff_threshold: u8,
+    /// How many times [print_frames](crate::print_frames) plays the animation, or None to
+    /// repeat it forever. Defaults to None.
+    pub loop_count: Option<u32>,
+    /// Crop the image of [print_poster](crate::print_poster) around its most detailed area
+    /// instead of its center. Defaults to false.
+    pub smart_crop: bool,
+    /// Print file names below the thumbnails of
+    /// [print_contact_sheet](crate::print_contact_sheet). Defaults to false.
+    pub captions: bool,
+}
+
+impl Config {
+    // Whether to print in truecolor, detecting support if the config does not say
+    pub(crate) fn truecolor_enabled(&self) -> bool {
+        self.truecolor.unwrap_or_else(utils::detect_truecolor)
+    }
+
+    // The color choice of the config, or the one of the environment if it is not set
+    pub(crate) fn resolved_color_choice(&self) -> ColorChoice {
+        self.color_choice.unwrap_or_else(utils::env_color_choice)
+    }
+
+    // Whether to save the cursor position before printing and restore it afterwards. Only
+    // terminals understand the escape codes, anywhere else they would just pollute the output
+    pub(crate) fn restores_cursor(&self) -> bool {
+        self.restore_cursor && utils::is_tty()
+    }
 }
 
 impl std::default::Default for Config {
@@ -37,16 +196,73 @@ impl std::default::Default for Config {
         Self {
             resize: true,
             transparent: false,
+            checkerboard_origin: (0, 0),
+            transparent_bg: None,
+            alpha_blend: false,
+            alpha_threshold: 0,
             absolute_offset: true,
             x: 0,
+            center: false,
             y: 0,
+            clamp_offsets: true,
             restore_cursor: false,
             width: None,
             height: None,
-            truecolor: utils::truecolor_available(),
+            truecolor: None,
+            color_choice: None,
+            merge_similar_halves: None,
+            force_ansi_grayscale: false,
+            dither: false,
+            vignette: None,
+            color_matrix: None,
+            opacity: 1.0,
+            grayscale: false,
+            invert: false,
+            edge_overlay: None,
+            partial_row_policy: PartialRowPolicy::Pad,
+            block_style: BlockStyle::HalfBlock,
+            max_in_flight_rows: None,
+            fallback_terminal_size: utils::DEFAULT_TERM_SIZE,
             use_kitty: true,
             use_iterm: true,
             use_sixel: true,
+            use_sextants: false,
+            use_braille: false,
+            braille_threshold: 128,
+            braille_color: None,
+            use_ascii: false,
+            ascii_ramp: None,
+            query_timeout: utils::DEFAULT_QUERY_TIMEOUT,
+            linear_compositing: false,
+            page_scroll_step: 1,
+            z_index: None,
+            diff_threshold: 0,
+            loop_count: None,
+            smart_crop: false,
+            captions: false,
         }
     }
 }
+
+/// Handling of the bottom terminal row when only a part of its pixels is covered by the image,
+/// for example the last line of an image with an odd height printed with half blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartialRowPolicy {
+    /// Fill the missing pixels with the background.
+    Pad,
+    /// Leave out the partial row.
+    Truncate,
+    /// Stretch the image vertically until the last row is full.
+    Upscale,
+}
+
+/// Characters the block printer draws the pixels with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStyle {
+    /// Two pixels in every cell, one above the other, drawn with half blocks.
+    HalfBlock,
+    /// One pixel in every cell, drawn as a space with a background color. Works with fonts
+    /// that have no block characters or show seams between half blocks, at half the vertical
+    /// resolution.
+    SpaceBackground,
+}
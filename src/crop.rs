@@ -0,0 +1,310 @@
+use crate::config::Config;
+use crate::edges::gradient_magnitude;
+use crate::error::{ViuError, ViuResult};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+/// Print an image so that it fills exactly `cols`x`rows` terminal cells.
+///
+/// The image is first cropped around its center to the aspect ratio of the target area and then
+/// scaled to fit it, so that nothing is stretched and no cell is left empty. This is handy for
+/// galleries, where every tile should have the same size regardless of the source image.
+/// With `smart_crop`, the cropped area is moved to where the image has the most detail instead.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_poster, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// print_poster(&img, 20, 10, &Config::default()).expect("Image printing failed.");
+/// ```
+pub fn print_poster(
+    img: &DynamicImage,
+    cols: u32,
+    rows: u32,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    let poster = poster_image(img, cols, rows, config.smart_crop)?;
+    let config = Config {
+        resize: false,
+        width: Some(cols),
+        height: Some(rows),
+        ..config.clone()
+    };
+    crate::print(&poster, &config)
+}
+
+// Crop the image to the aspect ratio of the poster and scale it to its size in pixels
+fn poster_image(
+    img: &DynamicImage,
+    cols: u32,
+    rows: u32,
+    smart_crop: bool,
+) -> ViuResult<DynamicImage> {
+    if cols == 0 || rows == 0 {
+        return Err(ViuError::InvalidConfiguration(
+            "poster dimensions must be positive".to_owned(),
+        ));
+    }
+    check_not_empty(img)?;
+
+    // a cell is two pixels tall
+    let (target_w, target_h) = (cols, 2 * rows);
+    let (mut x, mut y, w, h) = center_crop(img.dimensions(), (target_w, target_h));
+    if smart_crop {
+        let corner = busiest_crop(img, (w, h));
+        x = corner.0;
+        y = corner.1;
+    }
+    Ok(img
+        .crop_imm(x, y, w, h)
+        .resize_exact(target_w, target_h, FilterType::Triangle))
+}
+
+/// Print a magnified region of an image in a lens of `lens_cols`x`lens_rows` terminal cells.
+///
+/// The region is centered on the pixel at `center`, moved inside the image if needed, and
+/// sized so that every source pixel covers `zoom`x`zoom` pixels of the lens. Scaling uses the
+/// nearest neighbor, so individual pixels stay crisp for inspection.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_lens, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// print_lens(&img, (120, 80), 4.0, 20, 10, &Config::default()).expect("Image printing failed.");
+/// ```
+pub fn print_lens(
+    img: &DynamicImage,
+    center: (u32, u32),
+    zoom: f32,
+    lens_cols: u32,
+    lens_rows: u32,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    let lens = lens_image(img, center, zoom, lens_cols, lens_rows)?;
+    let config = Config {
+        resize: false,
+        width: Some(lens_cols),
+        height: Some(lens_rows),
+        ..config.clone()
+    };
+    crate::print(&lens, &config)
+}
+
+// Magnify the region shown by the lens to its size in pixels
+fn lens_image(
+    img: &DynamicImage,
+    center: (u32, u32),
+    zoom: f32,
+    lens_cols: u32,
+    lens_rows: u32,
+) -> ViuResult<DynamicImage> {
+    if lens_cols == 0 || lens_rows == 0 {
+        return Err(ViuError::InvalidConfiguration(
+            "lens dimensions must be positive".to_owned(),
+        ));
+    }
+    if zoom.is_nan() || zoom <= 0.0 {
+        return Err(ViuError::InvalidConfiguration(
+            "zoom must be positive".to_owned(),
+        ));
+    }
+    check_not_empty(img)?;
+
+    // a cell is two pixels tall
+    let (target_w, target_h) = (lens_cols, 2 * lens_rows);
+    let (x, y, w, h) = lens_region(img.dimensions(), center, zoom, (target_w, target_h));
+    Ok(img
+        .crop_imm(x, y, w, h)
+        .resize_exact(target_w, target_h, FilterType::Nearest))
+}
+
+// There is nothing to scale in an image without pixels
+fn check_not_empty(img: &DynamicImage) -> ViuResult {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return Err(ViuError::InvalidConfiguration(
+            "image must not be empty".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+// Find the top left corner of the `w`x`h` area with the largest sum of gradient magnitudes.
+// The area spans the whole image in at least one direction, so it only slides along the other.
+fn busiest_crop(img: &DynamicImage, (w, h): (u32, u32)) -> (u32, u32) {
+    let (img_w, img_h) = img.dimensions();
+    if w == img_w && h == img_h {
+        return (0, 0);
+    }
+
+    let magnitude = gradient_magnitude(img);
+    let mut sums = if w < img_w {
+        vec![0.0; img_w as usize]
+    } else {
+        vec![0.0; img_h as usize]
+    };
+    for (i, m) in magnitude.into_iter().enumerate() {
+        let (col, row) = (i % img_w as usize, i / img_w as usize);
+        sums[if w < img_w { col } else { row }] += m;
+    }
+
+    if w < img_w {
+        (best_window(&sums, w as usize) as u32, 0)
+    } else {
+        (0, best_window(&sums, h as usize) as u32)
+    }
+}
+
+// Start of the window of `len` values with the largest sum. Ties go to the most centered one.
+fn best_window(values: &[f32], len: usize) -> usize {
+    let center = (values.len() - len) / 2;
+    let mut sum: f32 = values[..len].iter().sum();
+    let mut best = (sum, 0usize);
+
+    for start in 1..=values.len() - len {
+        sum += values[start + len - 1] - values[start - 1];
+        let closer = start.abs_diff(center) < best.1.abs_diff(center);
+        if sum > best.0 || (sum == best.0 && closer) {
+            best = (sum, start);
+        }
+    }
+    best.1
+}
+
+// Find the area of the image shown in a lens of the target size, centered on `center` as far
+// as the image borders allow. Returns (x, y, width, height) of the area.
+fn lens_region(
+    (img_w, img_h): (u32, u32),
+    (center_x, center_y): (u32, u32),
+    zoom: f32,
+    (target_w, target_h): (u32, u32),
+) -> (u32, u32, u32, u32) {
+    let region =
+        |target: u32, size: u32| ((target as f32 / zoom).round() as u32).clamp(1, size.max(1));
+    let (w, h) = (region(target_w, img_w), region(target_h, img_h));
+
+    let x = center_x.saturating_sub(w / 2).min(img_w.saturating_sub(w));
+    let y = center_y.saturating_sub(h / 2).min(img_h.saturating_sub(h));
+    (x, y, w, h)
+}
+
+// Find the largest area with the target aspect ratio, centered within the image.
+// Returns (x, y, width, height) of the area, which is the whole image if it is empty.
+fn center_crop(
+    (img_w, img_h): (u32, u32),
+    (target_w, target_h): (u32, u32),
+) -> (u32, u32, u32, u32) {
+    if img_w == 0 || img_h == 0 {
+        return (0, 0, img_w, img_h);
+    }
+    let (img_w64, img_h64) = (img_w as u64, img_h as u64);
+    let (target_w64, target_h64) = (target_w as u64, target_h as u64);
+
+    if img_w64 * target_h64 > img_h64 * target_w64 {
+        // image is too wide, cut the sides
+        let w = std::cmp::max(1, (img_h64 * target_w64 / target_h64) as u32);
+        ((img_w - w) / 2, 0, w, img_h)
+    } else {
+        // image is too tall, cut the top and bottom
+        let h = std::cmp::max(1, (img_w64 * target_h64 / target_w64) as u32);
+        (0, (img_h - h) / 2, img_w, h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_center_crop() {
+        assert_eq!(center_crop((200, 100), (10, 10)), (50, 0, 100, 100));
+        assert_eq!(center_crop((100, 300), (10, 20)), (0, 50, 100, 200));
+        assert_eq!(center_crop((100, 50), (20, 10)), (0, 0, 100, 50));
+        // nothing to crop
+        assert_eq!(center_crop((0, 0), (10, 10)), (0, 0, 0, 0));
+        assert_eq!(center_crop((0, 5), (10, 10)), (0, 0, 0, 5));
+        assert_eq!(center_crop((5, 0), (10, 10)), (0, 0, 5, 0));
+    }
+
+    #[test]
+    fn test_best_window() {
+        assert_eq!(best_window(&[0.0, 5.0, 1.0, 0.0, 0.0], 2), 1);
+        assert_eq!(best_window(&[0.0, 0.0, 0.0, 1.0, 4.0], 3), 2);
+        // flat values keep the window centered
+        assert_eq!(best_window(&[1.0; 7], 3), 2);
+        assert_eq!(best_window(&[1.0; 3], 3), 0);
+    }
+
+    #[test]
+    fn test_busiest_crop() {
+        // a flat image with some detail on the right
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(40, 10, |x, y| {
+            if x > 32 && (x + y) % 2 == 0 {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        }));
+        assert_eq!(busiest_crop(&img, (10, 10)), (30, 0));
+        assert_eq!(busiest_crop(&img, (40, 10)), (0, 0));
+
+        let flat = DynamicImage::ImageRgba8(image::RgbaImage::new(10, 40));
+        assert_eq!(busiest_crop(&flat, (10, 10)), (0, 15));
+    }
+
+    #[test]
+    fn test_lens_region() {
+        assert_eq!(
+            lens_region((100, 100), (50, 50), 2.0, (20, 10)),
+            (45, 48, 10, 5)
+        );
+        // pushed back inside the image
+        assert_eq!(
+            lens_region((100, 100), (0, 99), 2.0, (20, 10)),
+            (0, 95, 10, 5)
+        );
+        // zooming out can't show more than the whole image
+        assert_eq!(lens_region((8, 4), (4, 2), 0.5, (20, 10)), (0, 0, 8, 4));
+    }
+
+    #[test]
+    fn test_lens_image() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([60 * x as u8, 60 * y as u8, 0, 255])
+        }));
+        // the 1x2 pixels at (2, 1), each one blown up to 2x2
+        let lens = lens_image(&img, (2, 2), 2.0, 2, 2).unwrap();
+        let expected = image::RgbaImage::from_fn(2, 4, |_, y| {
+            image::Rgba([120, 60 * (1 + y / 2) as u8, 0, 255])
+        });
+        assert_eq!(lens.to_rgba8(), expected);
+
+        assert!(lens_image(&img, (2, 2), 0.0, 2, 2).is_err());
+        assert!(lens_image(&img, (2, 2), 2.0, 0, 2).is_err());
+        let empty = DynamicImage::ImageRgba8(image::RgbaImage::new(0, 7));
+        assert!(lens_image(&empty, (0, 0), 3.0, 12, 5).is_err());
+    }
+
+    #[test]
+    fn test_poster_image() {
+        // a column of every color, and some detail on the right
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(6, 2, |x, y| {
+            if x == 5 && y == 0 {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([40 * x as u8, 0, 0, 255])
+            }
+        }));
+        // the poster is a single cell, so only the middle column is kept
+        let poster = poster_image(&img, 1, 1, false).unwrap();
+        assert_eq!(
+            poster.to_rgba8(),
+            image::RgbaImage::from_pixel(1, 2, image::Rgba([80, 0, 0, 255]))
+        );
+        // unless the crop follows the detail
+        let poster = poster_image(&img, 1, 1, true).unwrap();
+        assert_eq!(poster.get_pixel(0, 0), image::Rgba([255, 255, 255, 255]));
+
+        assert!(poster_image(&img, 0, 5, false).is_err());
+        let empty = DynamicImage::ImageRgba8(image::RgbaImage::new(0, 0));
+        assert!(poster_image(&empty, 12, 5, false).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::Config;
+use crate::error::{ViuError, ViuResult};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Print the difference between two images of equal dimensions as a heatmap and return the
+/// number of pixels that differ.
+///
+/// The difference of a pixel is the largest absolute difference among its RGBA channels.
+/// Only pixels whose difference exceeds `diff_threshold` from the [Config] count as different.
+/// They are colored from blue (small difference) to red (large difference), while all other
+/// pixels are left transparent.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_diff, Config};
+/// let expected = image::open("expected.png").expect("Could not open expected.png.");
+/// let actual = image::open("actual.png").expect("Could not open actual.png.");
+/// let changed = print_diff(&expected, &actual, &Config::default()).expect("Diff failed.");
+/// println!("{} pixels differ", changed);
+/// ```
+pub fn print_diff(a: &DynamicImage, b: &DynamicImage, config: &Config) -> ViuResult<usize> {
+    let (heatmap, changed) = diff_heatmap(a, b, config.diff_threshold)?;
+    crate::print(&DynamicImage::ImageRgba8(heatmap), config)?;
+    Ok(changed)
+}
+
+// Build the heatmap of the differences, together with the count of differing pixels
+fn diff_heatmap(
+    a: &DynamicImage,
+    b: &DynamicImage,
+    threshold: u8,
+) -> ViuResult<(RgbaImage, usize)> {
+    if a.dimensions() != b.dimensions() {
+        return Err(ViuError::InvalidConfiguration(format!(
+            "cannot diff images of different dimensions: {:?} and {:?}",
+            a.dimensions(),
+            b.dimensions()
+        )));
+    }
+
+    let (width, height) = a.dimensions();
+    let mut heatmap = RgbaImage::new(width, height);
+    let mut changed = 0;
+    for ((x, y, pa), (_, _, pb)) in a.pixels().zip(b.pixels()) {
+        let diff = (0..4)
+            .map(|c| (pa[c] as i16 - pb[c] as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap_or(0);
+        if diff > threshold {
+            changed += 1;
+            heatmap.put_pixel(x, y, heatmap_color(diff));
+        }
+    }
+    Ok((heatmap, changed))
+}
+
+// Map a difference to a color going blue -> cyan -> green -> yellow -> red
+fn heatmap_color(diff: u8) -> Rgba<u8> {
+    let t = diff as f32 / 255.0 * 4.0;
+    let ramp = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let (r, g, b) = match t as u32 {
+        0 => (0.0, t, 1.0),
+        1 => (0.0, 1.0, 2.0 - t),
+        2 => (t - 2.0, 1.0, 0.0),
+        _ => (1.0, 4.0 - t, 0.0),
+    };
+    Rgba([ramp(r), ramp(g), ramp(b), 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_heatmap() {
+        let a = RgbaImage::from_pixel(3, 2, Rgba([10, 10, 10, 255]));
+        let mut b = a.clone();
+        b.put_pixel(0, 0, Rgba([255, 10, 10, 255]));
+        b.put_pixel(2, 1, Rgba([15, 10, 10, 255]));
+        let a = DynamicImage::ImageRgba8(a);
+        let b = DynamicImage::ImageRgba8(b);
+
+        let (heatmap, changed) = diff_heatmap(&a, &b, 0).unwrap();
+        assert_eq!(changed, 2);
+        assert_eq!(*heatmap.get_pixel(0, 0), Rgba([255, 40, 0, 255]));
+        assert_eq!(heatmap.get_pixel(1, 0)[3], 0);
+
+        let (heatmap, changed) = diff_heatmap(&a, &b, 5).unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(heatmap.get_pixel(2, 1)[3], 0);
+    }
+
+    #[test]
+    fn test_diff_different_dimensions() {
+        let a = DynamicImage::ImageRgba8(RgbaImage::new(3, 2));
+        let b = DynamicImage::ImageRgba8(RgbaImage::new(2, 3));
+        assert!(diff_heatmap(&a, &b, 0).is_err());
+    }
+
+    #[test]
+    fn test_heatmap_color() {
+        assert_eq!(heatmap_color(0), Rgba([0, 0, 255, 255]));
+        assert_eq!(heatmap_color(255), Rgba([255, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,115 @@
+use image::{DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage};
+
+/// How the edges found by the `edge_overlay` [Config](crate::Config) option are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeStyle {
+    /// Color of the edges.
+    pub color: (u8, u8, u8),
+    /// Gradient strength, from 0 to 255, above which a pixel is considered an edge.
+    pub threshold: u8,
+    /// Draw only the edges and leave every other pixel transparent.
+    pub edges_only: bool,
+}
+
+impl std::default::Default for EdgeStyle {
+    fn default() -> Self {
+        Self {
+            color: (255, 255, 255),
+            threshold: 64,
+            edges_only: false,
+        }
+    }
+}
+
+// Strength of the luminance gradient of every pixel, computed with the Sobel operator and
+// scaled down so that it mostly fits in 0-255. Pixels outside the image repeat the border.
+pub fn gradient_magnitude(img: &DynamicImage) -> Vec<f32> {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    let mut magnitude = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: i32, dy: i32| luma_at(&luma, x as i32 + dx, y as i32 + dy);
+            let gx =
+                at(1, -1) + 2.0 * at(1, 0) + at(1, 1) - at(-1, -1) - 2.0 * at(-1, 0) - at(-1, 1);
+            let gy =
+                at(-1, 1) + 2.0 * at(0, 1) + at(1, 1) - at(-1, -1) - 2.0 * at(0, -1) - at(1, -1);
+            magnitude.push((gx * gx + gy * gy).sqrt() / 4.0);
+        }
+    }
+    magnitude
+}
+
+fn luma_at(luma: &GrayImage, x: i32, y: i32) -> f32 {
+    let x = x.clamp(0, luma.width() as i32 - 1) as u32;
+    let y = y.clamp(0, luma.height() as i32 - 1) as u32;
+    luma.get_pixel(x, y)[0] as f32
+}
+
+// Draw the edges of the image on top of it, or on their own
+pub fn overlay_edges(img: &DynamicImage, style: &EdgeStyle) -> DynamicImage {
+    let magnitude = gradient_magnitude(img);
+    let (width, height) = img.dimensions();
+    let (r, g, b) = style.color;
+
+    let mut out = RgbaImage::new(width, height);
+    for ((x, y, pixel), m) in img.pixels().zip(magnitude) {
+        let color = if m > style.threshold as f32 {
+            Rgba([r, g, b, 255])
+        } else if style.edges_only {
+            Rgba([0, 0, 0, 0])
+        } else {
+            pixel
+        };
+        out.put_pixel(x, y, color);
+    }
+    DynamicImage::ImageRgba8(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // left half black, right half white
+    fn split_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(6, 3, |x, _| {
+            if x < 3 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_gradient_magnitude() {
+        let magnitude = gradient_magnitude(&split_image());
+        // the row in the middle: flat, flat, edge, edge, flat, flat
+        let row = &magnitude[6..12];
+        assert_eq!(row[0], 0.0);
+        assert_eq!(row[1], 0.0);
+        assert_eq!(row[2], 255.0);
+        assert_eq!(row[3], 255.0);
+        assert_eq!(row[5], 0.0);
+    }
+
+    #[test]
+    fn test_overlay_edges() {
+        let style = EdgeStyle {
+            color: (255, 0, 0),
+            ..Default::default()
+        };
+        let out = overlay_edges(&split_image(), &style);
+        assert_eq!(out.get_pixel(0, 1), Rgba([0, 0, 0, 255]));
+        assert_eq!(out.get_pixel(2, 1), Rgba([255, 0, 0, 255]));
+
+        let style = EdgeStyle {
+            edges_only: true,
+            ..style
+        };
+        let out = overlay_edges(&split_image(), &style);
+        assert_eq!(out.get_pixel(0, 1)[3], 0);
+        assert_eq!(out.get_pixel(3, 1), Rgba([255, 0, 0, 255]));
+    }
+}
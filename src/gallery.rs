@@ -0,0 +1,191 @@
+use crate::config::Config;
+use crate::error::{ViuError, ViuResult};
+use crate::placement::Placement;
+use crate::utils::{cursor_row, is_tty};
+use crossterm::cursor::MoveToPreviousLine;
+use crossterm::execute;
+use image::imageops::FilterType;
+use image::GenericImageView;
+use std::io::Write;
+use std::path::PathBuf;
+
+// Empty cells between two thumbnails
+const GAP: u32 = 1;
+
+/// Print thumbnails of several image files in a grid with `cols` columns, starting at the
+/// cursor position. Returns a [Placement] for every printed thumbnail, whose id is the index of
+/// its path.
+///
+/// Every thumbnail is scaled to fit in `thumb_rows` rows and twice as many columns, which is a
+/// square area on screen. Files are decoded one at a time, right before they are printed. The
+/// ones that cannot be opened or decoded are left as empty cells. Set `captions` in the
+/// [Config] to print the file names below each grid row.
+///
+/// Placements are in screen coordinates when stdout is a terminal that reports the cursor
+/// position within `query_timeout`. Otherwise, their rows are counted from the line the grid
+/// started on.
+/// ## Example
+/// ```no_run
+/// use std::path::PathBuf;
+/// use viuer::{print_contact_sheet, Config};
+///
+/// let paths: Vec<PathBuf> = std::fs::read_dir("photos")
+///     .expect("Could not read directory.")
+///     .filter_map(|entry| entry.ok().map(|e| e.path()))
+///     .collect();
+/// let conf = Config {
+///     captions: true,
+///     ..Default::default()
+/// };
+/// print_contact_sheet(&paths, 4, 6, &conf).expect("Contact sheet printing failed.");
+/// ```
+pub fn print_contact_sheet(
+    paths: &[PathBuf],
+    cols: u32,
+    thumb_rows: u32,
+    config: &Config,
+) -> ViuResult<Vec<Placement>> {
+    if cols == 0 || thumb_rows == 0 {
+        return Err(ViuError::InvalidConfiguration(
+            "contact sheet needs at least one column and row".to_owned(),
+        ));
+    }
+
+    let thumb_cols = 2 * thumb_rows;
+    let mut stdout = std::io::stdout();
+    let mut sheet_rows = 0;
+    let mut placements = Vec::new();
+
+    for (grid_row_index, grid_row) in paths.chunks(cols as usize).enumerate() {
+        // height of the thumbnail printed last, the cursor is right below it
+        let mut printed_rows = 0;
+        let mut tallest = 0;
+
+        for (i, path) in grid_row.iter().enumerate() {
+            let img = match image::open(path) {
+                Ok(img) => img,
+                Err(_) => continue,
+            };
+
+            // keep the height even, so that every cell of the thumbnail holds two pixels
+            let thumb = img.resize(thumb_cols, 2 * thumb_rows, FilterType::Triangle);
+            let thumb = thumb.resize_exact(
+                thumb.width(),
+                std::cmp::max(2, thumb.height() & !1),
+                FilterType::Triangle,
+            );
+
+            // go back up to the top of the grid row. The lines were just printed, so unlike a
+            // negative y offset, this needs no cursor query to stay below the top of the screen
+            if printed_rows > 0 {
+                execute!(stdout, MoveToPreviousLine(printed_rows as u16))?;
+            }
+            // and right to the thumbnail's column
+            let thumb_config = Config {
+                resize: false,
+                absolute_offset: false,
+                restore_cursor: false,
+                x: config.x + (i as u32 * (thumb_cols + GAP)) as u16,
+                y: 0,
+                ..config.clone()
+            };
+            let (w, h) = crate::print(&thumb, &thumb_config)?;
+            placements.push(Placement {
+                id: grid_row_index * cols as usize + i,
+                x: thumb_config.x,
+                y: sheet_rows as u16,
+                cols: w,
+                rows: h,
+            });
+            printed_rows = h;
+            tallest = std::cmp::max(tallest, h);
+        }
+
+        // move below the tallest thumbnail of the grid row
+        for _ in printed_rows..tallest {
+            writeln!(stdout)?;
+        }
+        sheet_rows += tallest;
+
+        if config.captions {
+            writeln!(stdout, "{}", captions(grid_row, thumb_cols, config.x))?;
+            sheet_rows += 1;
+        }
+    }
+    stdout.flush()?;
+
+    // the cursor is now right below the grid, so its first line can be found even if the
+    // terminal scrolled while printing. Output that does not go to the terminal has no
+    // screen position to look up
+    let row = if is_tty() {
+        cursor_row(config.query_timeout).ok()
+    } else {
+        None
+    };
+    if let Some(row) = row {
+        let top = row as i64 - sheet_rows as i64;
+        for placement in placements.iter_mut() {
+            placement.y = std::cmp::max(0, top + placement.y as i64) as u16;
+        }
+    }
+    Ok(placements)
+}
+
+// Line with the file names of a grid row, each one aligned with its thumbnail
+fn captions(grid_row: &[PathBuf], thumb_cols: u32, x: u16) -> String {
+    let width = thumb_cols as usize;
+    let mut line = " ".repeat(x as usize);
+    for (i, path) in grid_row.iter().enumerate() {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name: String = name.chars().take(width).collect();
+        if i + 1 < grid_row.len() {
+            line.push_str(&format!("{:width$}", name, width = width + GAP as usize));
+        } else {
+            line.push_str(&name);
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captions() {
+        let paths = vec![PathBuf::from("dir/a.png"), PathBuf::from("long_name.jpg")];
+        assert_eq!(captions(&paths, 4, 2), "  a.pn long");
+    }
+
+    #[test]
+    fn test_print_contact_sheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (i, (w, h)) in [(20, 10), (10, 20), (8, 8)].iter().enumerate() {
+            let path = dir.path().join(format!("{}.png", i));
+            image::RgbaImage::new(*w, *h).save(&path).unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.path().join("missing.png"));
+
+        let config = Config {
+            captions: true,
+            ..Default::default()
+        };
+        let placements = print_contact_sheet(&paths, 2, 3, &config).unwrap();
+        let placed: Vec<_> = placements
+            .iter()
+            .map(|p| (p.id, p.x, p.cols, p.rows))
+            .collect();
+        // the missing file is skipped, the others are 7 columns apart
+        assert_eq!(placed, vec![(0, 0, 6, 1), (1, 7, 3, 3), (2, 0, 6, 3)]);
+        // the second grid row starts below the tallest thumbnail and the captions
+        assert_eq!(placements[2].y - placements[0].y, 4);
+        assert_eq!(placements[0].y, placements[1].y);
+
+        assert!(print_contact_sheet(&paths, 0, 3, &config).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::config::Config;
+use crate::error::ViuResult;
+use crate::placement::Placement;
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::queue;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use termcolor::{Buffer, BufferWriter, Color, ColorSpec, WriteColor};
+
+// Time for the highlight to go from dim to bright and back again
+const PULSE_PERIOD: Duration = Duration::from_millis(1500);
+const HIGHLIGHT_COLOR: (u8, u8, u8) = (255, 200, 0);
+// Brightness of the highlight at the dimmest point of a pulse
+const MIN_BRIGHTNESS: f32 = 0.3;
+
+/// Draw a border around the cells of a [Placement] to mark it as selected, for example in a
+/// gallery navigated with the keyboard.
+///
+/// The color of the border depends on the time of the call and pulses every 1.5 seconds, so
+/// calling this function repeatedly, every few tens of milliseconds, makes the border breathe.
+/// Only the border cells are written, the image is left alone. Sides that would fall outside
+/// the screen are not drawn. The cursor is restored afterwards.
+/// ## Example
+/// ```no_run
+/// use viuer::{highlight, print_placed, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let conf = Config {
+///     x: 2,
+///     y: 1,
+///     width: Some(20),
+///     ..Default::default()
+/// };
+/// let placement = print_placed(&img, 0, &conf).expect("Image printing failed.");
+/// loop {
+///     highlight(&placement, &conf).expect("Highlighting failed.");
+///     std::thread::sleep(std::time::Duration::from_millis(50));
+/// }
+/// ```
+pub fn highlight(placement: &Placement, config: &Config) -> ViuResult {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let color = pulse_color(elapsed);
+
+    let writer = BufferWriter::stdout(config.resolved_color_choice());
+    let mut buffer = writer.buffer();
+    queue!(buffer, SavePosition)?;
+    draw_border(&mut buffer, placement, color, config.truecolor_enabled())?;
+    queue!(buffer, RestorePosition)?;
+    writer.print(&buffer)?;
+    Ok(())
+}
+
+// Color of the highlight at a point in time, following a sine wave between dim and bright
+fn pulse_color(elapsed: Duration) -> (u8, u8, u8) {
+    let phase =
+        (elapsed.as_millis() % PULSE_PERIOD.as_millis()) as f32 / PULSE_PERIOD.as_millis() as f32;
+    let wave = 0.5 - 0.5 * (phase * 2.0 * std::f32::consts::PI).cos();
+    let brightness = MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * wave;
+
+    let scale = |c: u8| (c as f32 * brightness).round() as u8;
+    let (r, g, b) = HIGHLIGHT_COLOR;
+    (scale(r), scale(g), scale(b))
+}
+
+// Draw a box one cell outside of the placement
+fn draw_border(
+    out: &mut Buffer,
+    placement: &Placement,
+    (r, g, b): (u8, u8, u8),
+    truecolor: bool,
+) -> ViuResult {
+    let color = if truecolor {
+        Color::Rgb(r, g, b)
+    } else {
+        Color::Ansi256(ansi_colours::ansi256_from_rgb((r, g, b)))
+    };
+    out.set_color(ColorSpec::new().set_fg(Some(color)))?;
+
+    let (x, y) = (placement.x as i64, placement.y as i64);
+    let (left, top) = (x - 1, y - 1);
+    let (right, bottom) = (x + placement.cols as i64, y + placement.rows as i64);
+    let max = u16::MAX as i64;
+
+    let horizontal = |out: &mut Buffer, row: i64, corners: (&str, &str)| -> ViuResult {
+        if row < 0 || row > max {
+            return Ok(());
+        }
+        let start = std::cmp::max(left, 0);
+        queue!(out, MoveTo(start as u16, row as u16))?;
+        for col in start..=std::cmp::min(right, max) {
+            let c = if col == left {
+                corners.0
+            } else if col == right {
+                corners.1
+            } else {
+                "\u{2500}"
+            };
+            write!(out, "{}", c)?;
+        }
+        Ok(())
+    };
+    horizontal(out, top, ("\u{250c}", "\u{2510}"))?;
+    horizontal(out, bottom, ("\u{2514}", "\u{2518}"))?;
+
+    for row in std::cmp::max(y, 0)..std::cmp::min(bottom, max + 1) {
+        for &col in [left, right].iter() {
+            if col >= 0 && col <= max {
+                queue!(out, MoveTo(col as u16, row as u16))?;
+                write!(out, "\u{2502}")?;
+            }
+        }
+    }
+
+    out.reset()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_color() {
+        let dim = pulse_color(Duration::from_millis(0));
+        let bright = pulse_color(PULSE_PERIOD / 2);
+        assert_eq!(bright, HIGHLIGHT_COLOR);
+        assert_eq!(dim, (77, 60, 0));
+        assert_eq!(pulse_color(PULSE_PERIOD * 3), dim);
+    }
+
+    #[test]
+    fn test_draw_border() {
+        let placement = Placement {
+            id: 0,
+            x: 0,
+            y: 1,
+            cols: 2,
+            rows: 1,
+        };
+        let mut buffer = Buffer::no_color();
+        draw_border(&mut buffer, &placement, (255, 0, 0), true).unwrap();
+        // the left side is off screen
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "\x1b[1;1H\u{2500}\u{2500}\u{2510}\x1b[3;1H\u{2500}\u{2500}\u{2518}\x1b[2;3H\u{2502}"
+        );
+    }
+}
@@ -30,18 +30,45 @@ use crossterm::execute;
 use image::DynamicImage;
 use printer::Printer;
 use std::io::Write;
+use termcolor::ColorChoice;
 
+mod animation;
+mod color_depth;
+mod compose;
 mod config;
+mod crop;
+mod diff;
+mod edges;
 mod error;
+mod gallery;
+mod highlight;
+mod palette;
+mod placement;
 mod printer;
+mod scanline;
 mod utils;
+mod viewport;
+mod wallpaper;
 
-pub use config::Config;
+pub use animation::print_frames;
+pub use color_depth::{recommend_color_depth, ColorDepth};
+pub use config::{BlockStyle, Config, PartialRowPolicy};
+pub use crop::{print_lens, print_poster};
+pub use diff::print_diff;
+pub use edges::EdgeStyle;
 pub use error::{ViuError, ViuResult};
+pub use gallery::print_contact_sheet;
+pub use highlight::highlight;
+pub use palette::print_with_palette;
+pub use placement::{hit_test, print_after, print_placed, Placement};
 pub use printer::{
-    get_kitty_support, get_sixel_support, is_iterm_supported, resize, KittySupport, SixelSupport,
+    fit_dimensions, get_kitty_support, get_sixel_support, is_iterm_supported, print_to, render,
+    render_to_bytes, resize, GraphicsBackend, KittySupport, SixelSupport,
 };
-pub use utils::terminal_size;
+pub use scanline::print_scanline;
+pub use utils::{detect_foreground_color, detect_truecolor, terminal_size};
+pub use viewport::{pan_down, pan_left, pan_right, pan_up, print_viewport};
+pub use wallpaper::with_wallpaper;
 
 /// Default printing method. Uses either iTerm or Kitty graphics protocol, if supported,
 /// and half blocks otherwise.
@@ -68,7 +95,7 @@ pub use utils::terminal_size;
 /// ```
 pub fn print(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
     let mut stdout = std::io::stdout();
-    if config.restore_cursor {
+    if config.restores_cursor() {
         execute!(&mut stdout, crossterm::cursor::SavePosition)?;
     }
 
@@ -76,7 +103,7 @@ pub fn print(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
 
     let (w, h) = printer.print(img, config)?;
 
-    if config.restore_cursor {
+    if config.restores_cursor() {
         execute!(&mut stdout, crossterm::cursor::RestorePosition)?;
     };
 
@@ -99,7 +126,7 @@ pub fn print(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
 /// ```
 pub fn print_from_file(filename: &str, config: &Config) -> ViuResult<(u32, u32)> {
     let mut stdout = std::io::stdout();
-    if config.restore_cursor {
+    if config.restores_cursor() {
         execute!(&mut stdout, crossterm::cursor::SavePosition)?;
     }
 
@@ -107,26 +134,121 @@ pub fn print_from_file(filename: &str, config: &Config) -> ViuResult<(u32, u32)>
 
     let (w, h) = printer.print_from_file(filename, config)?;
 
-    if config.restore_cursor {
+    if config.restores_cursor() {
         execute!(&mut stdout, crossterm::cursor::RestorePosition)?;
     };
 
     Ok((w, h))
 }
 
+/// Alpha composite several images on top of each other and print the result in one go.
+///
+/// Layers are ordered from bottom to top and all of them start at the top left corner.
+/// The printed image is as large as the biggest layer. Areas left transparent after compositing
+/// are handled like transparent pixels in [print](fn@print). Set `linear_compositing` in the [Config]
+/// to blend in linear light.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_layers, Config};
+/// let background = image::open("background.png").expect("Could not open background.");
+/// let sprite = image::open("sprite.png").expect("Could not open sprite.");
+/// print_layers(&[&background, &sprite], &Config::default()).expect("Image printing failed.");
+/// ```
+pub fn print_layers(layers: &[&DynamicImage], config: &Config) -> ViuResult<(u32, u32)> {
+    let img = compose::composite_layers(layers, config.linear_compositing)?;
+    print(&img, config)
+}
+
+/// Remove an image that was printed with the same config from the terminal.
+///
+/// When the Kitty graphics protocol is in use, all images visible on the screen are deleted.
+/// Otherwise, the area the image occupied is overwritten with blank cells. It is computed from
+/// the x and y offsets together with `width` and `height`, falling back to the terminal size
+/// when they are not specified.
+/// ## Example
+/// ```no_run
+/// use viuer::{clear_graphics, print_from_file, Config};
+/// let conf = Config {
+///     width: Some(40),
+///     height: Some(20),
+///     ..Default::default()
+/// };
+/// print_from_file("img.jpg", &conf).expect("Image printing failed.");
+/// // ...
+/// clear_graphics(&conf).expect("Clearing the image failed.");
+/// ```
+pub fn clear_graphics(config: &Config) -> ViuResult {
+    choose_printer(config).clear(config)
+}
+
 // Choose the appropriate printer to use based on user config and availability
 fn choose_printer(config: &Config) -> Box<dyn Printer> {
     if config.use_iterm && is_iterm_supported() {
         // print!("We are using the iterm printer");
         Box::new(printer::iTermPrinter {})
-    } else if config.use_kitty && get_kitty_support() != KittySupport::None {
+    } else if config.use_kitty && printer::kitty_support(config.query_timeout) != KittySupport::None
+    {
         // print!("We are using the kitty printer");
         Box::new(printer::KittyPrinter {})
-    } else if config.use_sixel && get_sixel_support() != SixelSupport::None {
+    } else if config.use_sixel && printer::sixel_support(config.query_timeout) != SixelSupport::None
+    {
         // print!("We are using the sixel printer");
         Box::new(printer::SixelPrinter {})
     } else {
-        // print!("We are using the block printer");
-        Box::new(printer::BlockPrinter {})
+        match text_printer(config) {
+            TextPrinter::Ascii => Box::new(printer::AsciiPrinter {}),
+            TextPrinter::Braille => Box::new(printer::BraillePrinter {}),
+            TextPrinter::Sextant => Box::new(printer::SextantPrinter {}),
+            // print!("We are using the block printer");
+            TextPrinter::Block => Box::new(printer::BlockPrinter {}),
+        }
+    }
+}
+
+// Printers that draw with characters, used when no graphics protocol is available
+#[derive(Debug, PartialEq)]
+enum TextPrinter {
+    Ascii,
+    Braille,
+    Sextant,
+    Block,
+}
+
+fn text_printer(config: &Config) -> TextPrinter {
+    if config.resolved_color_choice() == ColorChoice::Never {
+        // the other printers write escape codes, which files and pipes do not understand
+        TextPrinter::Ascii
+    } else if config.use_braille {
+        TextPrinter::Braille
+    } else if config.use_ascii {
+        TextPrinter::Ascii
+    } else if config.use_sextants {
+        TextPrinter::Sextant
+    } else {
+        TextPrinter::Block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_printer() {
+        let mut config = Config {
+            color_choice: Some(ColorChoice::Always),
+            ..Default::default()
+        };
+        assert_eq!(text_printer(&config), TextPrinter::Block);
+        config.use_sextants = true;
+        assert_eq!(text_printer(&config), TextPrinter::Sextant);
+        config.use_braille = true;
+        assert_eq!(text_printer(&config), TextPrinter::Braille);
+
+        // without colors, plain text wins over the other modes
+        config.color_choice = Some(ColorChoice::Never);
+        assert_eq!(text_printer(&config), TextPrinter::Ascii);
+        config.use_braille = false;
+        assert_eq!(text_printer(&config), TextPrinter::Ascii);
     }
 }
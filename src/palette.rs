@@ -0,0 +1,191 @@
+use crate::config::Config;
+use crate::error::{ViuError, ViuResult};
+use crossterm::cursor::{RestorePosition, SavePosition};
+use crossterm::execute;
+use image::{DynamicImage, GenericImageView};
+use std::io::Write;
+use termcolor::{BufferWriter, Color, ColorSpec, WriteColor};
+
+// Most pixels looked at when extracting the palette, to keep large images fast
+const MAX_SAMPLES: u32 = 1 << 16;
+
+/// Print an image followed by a row of swatches with its `swatch_count` most prominent colors,
+/// each labeled with its hex code. Returns the dimensions of the image and the swatch row.
+///
+/// The colors are extracted with median cut quantization and ordered by the share of the
+/// image they represent. Transparent pixels are ignored.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_with_palette, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// print_with_palette(&img, 5, &Config::default()).expect("Image printing failed.");
+/// ```
+pub fn print_with_palette(
+    img: &DynamicImage,
+    swatch_count: usize,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    if swatch_count == 0 {
+        return Err(ViuError::InvalidConfiguration(
+            "at least one swatch is needed".to_owned(),
+        ));
+    }
+
+    let mut stdout = std::io::stdout();
+    if config.restores_cursor() {
+        execute!(stdout, SavePosition)?;
+    }
+
+    // the swatches go right below the image
+    let image_config = Config {
+        restore_cursor: false,
+        ..config.clone()
+    };
+    let (w, h) = crate::print(img, &image_config)?;
+
+    let palette = median_cut(img, swatch_count);
+    let writer = BufferWriter::stdout(config.resolved_color_choice());
+    let mut buffer = writer.buffer();
+    write_swatches(&mut buffer, &palette, config)?;
+    writer.print(&buffer)?;
+
+    if config.restores_cursor() {
+        execute!(stdout, RestorePosition)?;
+    }
+    Ok((w, h + 1))
+}
+
+// Write a line with a colored block and the hex code of every color, starting at column x
+fn write_swatches(
+    out: &mut impl WriteColor,
+    palette: &[(u8, u8, u8)],
+    config: &Config,
+) -> ViuResult {
+    write!(out, "{}", " ".repeat(config.x as usize))?;
+    for (i, &(r, g, b)) in palette.iter().enumerate() {
+        let color = if config.truecolor_enabled() {
+            Color::Rgb(r, g, b)
+        } else {
+            Color::Ansi256(ansi_colours::ansi256_from_rgb((r, g, b)))
+        };
+        out.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        write!(out, "\u{2588}\u{2588}")?;
+        out.reset()?;
+        write!(out, " #{:02x}{:02x}{:02x}", r, g, b)?;
+        if i + 1 < palette.len() {
+            write!(out, "  ")?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+// Split the colors of the image into at most `count` boxes, always cutting the box with the
+// widest channel range at its median, and return the average color of every box, starting
+// with the boxes holding the most pixels
+fn median_cut(img: &DynamicImage, count: usize) -> Vec<(u8, u8, u8)> {
+    let (width, height) = img.dimensions();
+    let step = std::cmp::max(1, (width * height / MAX_SAMPLES) as usize);
+    let colors: Vec<[u8; 3]> = img
+        .pixels()
+        .step_by(step)
+        .filter(|(_, _, p)| p[3] != 0)
+        .map(|(_, _, p)| [p[0], p[1], p[2]])
+        .collect();
+
+    let mut boxes = vec![colors];
+    if boxes[0].is_empty() {
+        return Vec::new();
+    }
+
+    while boxes.len() < count {
+        // find the box and channel with the widest range
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .max_by_key(|&(_, _, range)| range)
+            .unwrap();
+        if range == 0 {
+            // every box has a single color left
+            break;
+        }
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|c| c[channel]);
+        // keep equal values on the same side of the cut
+        let median = colors[colors.len() / 2][channel];
+        let mut cut = colors.partition_point(|c| c[channel] < median);
+        if cut == 0 {
+            cut = colors.partition_point(|c| c[channel] <= median);
+        }
+        let upper = colors.split_off(cut);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|b| std::cmp::Reverse(b.len()));
+    boxes.iter().map(|b| average(b)).collect()
+}
+
+// Channel with the largest difference between its highest and lowest value, and that difference
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|c| c[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|c| c[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+fn average(colors: &[[u8; 3]]) -> (u8, u8, u8) {
+    let n = colors.len() as u64;
+    let sum = |channel: usize| colors.iter().map(|c| c[channel] as u64).sum::<u64>();
+    let avg = |channel: usize| ((sum(channel) + n / 2) / n) as u8;
+    (avg(0), avg(1), avg(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_median_cut() {
+        // three quarters red, one quarter blue
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+            if x < 3 {
+                Rgba([250, 10, 10, 255])
+            } else {
+                Rgba([10, 10, 250, 255])
+            }
+        }));
+        assert_eq!(median_cut(&img, 2), vec![(250, 10, 10), (10, 10, 250)]);
+        // only two colors to find
+        assert_eq!(median_cut(&img, 5).len(), 2);
+        assert_eq!(median_cut(&img, 1), vec![(190, 10, 70)]);
+
+        let transparent = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        assert!(median_cut(&transparent, 3).is_empty());
+    }
+
+    #[test]
+    fn test_write_swatches() {
+        let config = Config {
+            x: 2,
+            truecolor: Some(true),
+            ..Default::default()
+        };
+        let mut buffer = termcolor::Buffer::no_color();
+        write_swatches(&mut buffer, &[(255, 136, 0), (0, 0, 0)], &config).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "  \u{2588}\u{2588} #ff8800  \u{2588}\u{2588} #000000\n"
+        );
+    }
+}
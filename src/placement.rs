@@ -0,0 +1,238 @@
+use crate::config::Config;
+use crate::error::{ViuError, ViuResult};
+use crate::printer::{best_fit, left_margin};
+use crate::utils::{cursor_row, terminal_size_or};
+use image::{DynamicImage, GenericImageView};
+
+/// Where a printed image landed on the screen, in terminal cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// Identifier of the image, chosen by the caller or given by the printing function.
+    pub id: usize,
+    /// Column of the image's top left corner.
+    pub x: u16,
+    /// Row of the image's top left corner.
+    pub y: u16,
+    /// Number of columns the image occupies.
+    pub cols: u32,
+    /// Number of rows the image occupies.
+    pub rows: u32,
+}
+
+/// Print an image like [print](fn@crate::print) and return where it landed on the screen.
+///
+/// With `absolute_offset`, the position follows directly from the offsets in the [Config].
+/// Otherwise the terminal is asked for the cursor position, which fails if it does not answer
+/// within `query_timeout`.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_placed, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let placement = print_placed(&img, 0, &Config::default()).expect("Image printing failed.");
+/// println!("Image covers {}x{} cells", placement.cols, placement.rows);
+/// ```
+pub fn print_placed(img: &DynamicImage, id: usize, config: &Config) -> ViuResult<Placement> {
+    let (cols, rows) = crate::print(img, config)?;
+
+    let y = if config.absolute_offset {
+        config.y as u16
+    } else if config.restores_cursor() {
+        // the cursor is back on the line the offset was applied to
+        let row = cursor_row(config.query_timeout)? as i32;
+        std::cmp::max(0, row + config.y as i32) as u16
+    } else {
+        // the cursor is on the line right below the image, even if the terminal scrolled
+        let row = cursor_row(config.query_timeout)?;
+        row.saturating_sub(rows as u16)
+    };
+
+    Ok(Placement {
+        id,
+        // the same column the printers start the rows at
+        x: left_margin(cols, config),
+        y,
+        cols,
+        rows,
+    })
+}
+
+/// Print an image to the right of the last of the `placed` images, leaving `gap` empty columns
+/// between them. If the image would not fit in the terminal's width, it starts a new line
+/// instead, at the column given by `x` in the [Config], below the tallest image on the line of
+/// the last one. Fails if nothing has been placed yet.
+///
+/// The image is printed with absolute offsets and its [Placement] gets the id following the
+/// last one. Collecting the placements lays out a gallery of images of any size.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_after, print_placed, Config};
+/// let a = image::open("a.jpg").expect("Could not open image.");
+/// let b = image::open("b.jpg").expect("Could not open image.");
+/// let conf = Config {
+///     width: Some(20),
+///     ..Default::default()
+/// };
+/// let mut placed = vec![print_placed(&a, 0, &conf).expect("Image printing failed.")];
+/// placed.push(print_after(&b, &placed, 1, &conf).expect("Image printing failed."));
+/// ```
+pub fn print_after(
+    img: &DynamicImage,
+    placed: &[Placement],
+    gap: u16,
+    config: &Config,
+) -> ViuResult<Placement> {
+    let prev = placed
+        .last()
+        .ok_or_else(|| ViuError::InvalidConfiguration("no placement to print after".to_owned()))?;
+    let cols = if config.resize {
+        best_fit(img, config).0
+    } else {
+        img.width()
+    };
+    let (x, y) = next_position(
+        placed,
+        gap,
+        cols,
+        terminal_size_or(config.fallback_terminal_size).0,
+        config.x,
+    );
+
+    let config = Config {
+        absolute_offset: true,
+        x,
+        y: y as i16,
+        ..config.clone()
+    };
+    print_placed(img, prev.id + 1, &config)
+}
+
+// Top left corner of an image `cols` wide that follows the last of the non-empty `placed` in a
+// left to right flow. A new line starts below the bottom of every image on the current one
+fn next_position(
+    placed: &[Placement],
+    gap: u16,
+    cols: u32,
+    term_width: u16,
+    left: u16,
+) -> (u16, u16) {
+    let prev = &placed[placed.len() - 1];
+    let x = prev.x as u32 + prev.cols + gap as u32;
+    if x + cols <= term_width as u32 {
+        (x as u16, prev.y)
+    } else {
+        let bottom = placed
+            .iter()
+            .filter(|p| p.y == prev.y)
+            .map(|p| p.y as u32 + p.rows)
+            .max()
+            .unwrap_or(prev.y as u32);
+        (left, std::cmp::min(bottom, u16::MAX as u32) as u16)
+    }
+}
+
+/// Find which image covers the terminal cell at (`col`, `row`), for example the target of a
+/// mouse click reported by [crossterm]. Returns the id of the matching [Placement].
+///
+/// When images overlap, the one placed last is considered on top.
+/// ## Example
+/// ```
+/// use viuer::{hit_test, Placement};
+/// let placements = vec![Placement { id: 7, x: 0, y: 0, cols: 10, rows: 5 }];
+/// assert_eq!(hit_test(&placements, 3, 4), Some(7));
+/// assert_eq!(hit_test(&placements, 10, 4), None);
+/// ```
+pub fn hit_test(placements: &[Placement], col: u16, row: u16) -> Option<usize> {
+    placements
+        .iter()
+        .rev()
+        .find(|p| p.contains(col, row))
+        .map(|p| p.id)
+}
+
+impl Placement {
+    /// Check whether the image covers the terminal cell at (`col`, `row`).
+    pub fn contains(&self, col: u16, row: u16) -> bool {
+        let (col, row) = (col as u32, row as u32);
+        let (x, y) = (self.x as u32, self.y as u32);
+        col >= x && col < x + self.cols && row >= y && row < y + self.rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(id: usize, x: u16, y: u16, cols: u32, rows: u32) -> Placement {
+        Placement {
+            id,
+            x,
+            y,
+            cols,
+            rows,
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let p = placement(0, 2, 3, 4, 2);
+        assert!(p.contains(2, 3));
+        assert!(p.contains(5, 4));
+        assert!(!p.contains(6, 4));
+        assert!(!p.contains(5, 5));
+        assert!(!p.contains(1, 3));
+        assert!(!placement(0, 0, 0, 0, 0).contains(0, 0));
+    }
+
+    #[test]
+    fn test_next_position() {
+        let prev = [placement(0, 2, 3, 10, 5)];
+        assert_eq!(next_position(&prev, 1, 20, 80, 2), (13, 3));
+        assert_eq!(next_position(&prev, 0, 68, 80, 2), (12, 3));
+        // does not fit, go to the next line
+        assert_eq!(next_position(&prev, 1, 68, 80, 2), (2, 8));
+    }
+
+    #[test]
+    fn test_next_position_mixed_heights() {
+        // a tall image followed by a short one, on a line below an even taller one
+        let placed = [
+            placement(0, 0, 0, 10, 20),
+            placement(1, 0, 20, 10, 8),
+            placement(2, 11, 20, 10, 3),
+        ];
+        assert_eq!(next_position(&placed, 1, 30, 40, 0), (0, 28));
+        assert_eq!(next_position(&placed, 1, 10, 40, 0), (22, 20));
+    }
+
+    #[test]
+    fn test_print_placed_centered() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 10));
+        let mut config = Config {
+            resize: false,
+            x: 4,
+            y: 2,
+            ..Default::default()
+        };
+        let placed = print_placed(&img, 1, &config).unwrap();
+        assert_eq!(placed, placement(1, 4, 2, 20, 5));
+        // the terminal is 80 columns wide in tests
+        config.center = true;
+        let placed = print_placed(&img, 1, &config).unwrap();
+        assert_eq!(placed, placement(1, 30, 2, 20, 5));
+    }
+
+    #[test]
+    fn test_hit_test() {
+        let placements = vec![
+            placement(1, 0, 0, 10, 5),
+            placement(2, 11, 0, 10, 5),
+            placement(3, 5, 2, 10, 5),
+        ];
+        assert_eq!(hit_test(&placements, 0, 0), Some(1));
+        assert_eq!(hit_test(&placements, 12, 1), Some(2));
+        // overlapping area belongs to the last placement
+        assert_eq!(hit_test(&placements, 6, 3), Some(3));
+        assert_eq!(hit_test(&placements, 10, 0), None);
+        assert_eq!(hit_test(&[], 0, 0), None);
+    }
+}
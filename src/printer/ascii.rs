@@ -0,0 +1,185 @@
+use crate::error::ViuResult;
+use crate::printer::block::{luminance, pixel_rgb, print_buffer};
+use crate::printer::Printer;
+use crate::utils::is_tty;
+use crate::Config;
+
+use image::{DynamicImage, GenericImageView};
+use std::io::Write;
+use termcolor::{BufferWriter, ColorChoice};
+
+// Characters used when the config has no ascii_ramp, from the darkest to the brightest
+const DEFAULT_ASCII_RAMP: &str = " .:-=+*#%@";
+
+pub struct AsciiPrinter {}
+
+impl Printer for AsciiPrinter {
+    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+        // plain text only, the image has to be recognizable without any colors
+        let stdout = BufferWriter::stdout(ColorChoice::Never);
+        let mut out_buffer = stdout.buffer();
+        write_rows(&mut out_buffer, img, config, is_tty(), |out_buffer| {
+            print_buffer(&stdout, out_buffer)
+        })
+    }
+}
+
+// Write the image as text to out_buffer, calling `row_done` after every row. The cursor is
+// moved to the y offset with escape codes only if `escapes` is set, since files and pipes do
+// not understand them. Without them, a relative y offset of at least 0 is written as newlines
+// and any other one is left out.
+fn write_rows<W: Write>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    escapes: bool,
+    mut row_done: impl FnMut(&mut W) -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    if escapes {
+        super::move_to_first_line(out_buffer, config, true)?;
+    } else if !config.absolute_offset && config.y > 0 {
+        write!(out_buffer, "{}", "\n".repeat(config.y as usize))?;
+    }
+
+    // a cell holds a single pixel
+    let prepared = super::prepare_image(img, config, (1, 1));
+    let img: &DynamicImage = &prepared;
+    let (width, height) = img.dimensions();
+    let x = super::left_margin(width, config);
+
+    let ramp: Vec<char> = config
+        .ascii_ramp
+        .as_deref()
+        .filter(|ramp| !ramp.is_empty())
+        .unwrap_or(DEFAULT_ASCII_RAMP)
+        .chars()
+        .collect();
+
+    for y in 0..height {
+        // spaces instead of moving right, so that no escape codes are needed
+        write!(out_buffer, "{}", " ".repeat(x as usize))?;
+        for x in 0..width {
+            write!(out_buffer, "{}", ascii_char(img, x, y, &ramp, config))?;
+        }
+        writeln!(out_buffer)?;
+        row_done(out_buffer)?;
+    }
+
+    Ok((width, height))
+}
+
+// Character of the ramp matching the luma of the pixel at (x, y). Transparent pixels are spaces.
+fn ascii_char(img: &DynamicImage, x: u32, y: u32, ramp: &[char], config: &Config) -> char {
+    let pixel = img.get_pixel(x, y);
+    if pixel[3] <= config.alpha_threshold {
+        return ' ';
+    }
+    match pixel_rgb((x, y, pixel), img.dimensions(), config) {
+        Some(rgb) => {
+            let steps = (ramp.len() - 1) as u32;
+            ramp[((luminance(rgb) as u32 * steps + 127) / 255) as usize]
+        }
+        None => ' ',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_ascii_char() {
+        let ramp: Vec<char> = DEFAULT_ASCII_RAMP.chars().collect();
+        let mut img = image::RgbaImage::new(4, 1);
+        img.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        img.put_pixel(1, 0, Rgba([128, 128, 128, 255]));
+        img.put_pixel(2, 0, Rgba([255, 255, 255, 255]));
+        let img = DynamicImage::ImageRgba8(img);
+        let config = Config::default();
+
+        assert_eq!(ascii_char(&img, 0, 0, &ramp, &config), ' ');
+        assert_eq!(ascii_char(&img, 1, 0, &ramp, &config), '+');
+        assert_eq!(ascii_char(&img, 2, 0, &ramp, &config), '@');
+        // transparent, even with the checkerboard enabled
+        assert_eq!(ascii_char(&img, 3, 0, &ramp, &config), ' ');
+
+        let ramp = ['a', 'b'];
+        assert_eq!(ascii_char(&img, 2, 0, &ramp, &config), 'b');
+    }
+
+    #[test]
+    fn test_write_rows_offsets() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            2,
+            2,
+            Rgba([255, 255, 255, 255]),
+        ));
+        let mut config = Config {
+            resize: false,
+            absolute_offset: false,
+            x: 2,
+            y: 3,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_rows(&mut out, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\n\n\n  @@\n  @@\n");
+
+        // the move to the offset needs escape codes
+        let mut out = Vec::new();
+        write_rows(&mut out, &img, &config, true, |_| Ok(())).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("\x1b[0G  @@\n  @@\n"));
+
+        config.absolute_offset = true;
+        let mut out = Vec::new();
+        write_rows(&mut out, &img, &config, true, |_| Ok(())).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("\x1b[4;1H"));
+    }
+
+    #[test]
+    fn test_no_escapes_without_colors() {
+        // what print falls back to when colors are disabled and stdout is not a terminal
+        let img =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(3, 3, Rgba([90, 20, 200, 128])));
+        let config = Config {
+            color_choice: Some(ColorChoice::Never),
+            use_braille: true,
+            absolute_offset: false,
+            x: 1,
+            y: 1,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_rows(&mut out, &img, &config, false, |_| Ok(())).unwrap();
+        assert!(!out.is_empty());
+        assert!(!out.contains(&0x1b));
+    }
+
+    #[test]
+    fn test_write_rows_ramp() {
+        // a gradient from black to white over a midtone row
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(5, 2, |x, y| {
+            let v = if y == 0 { x as u8 * 63 } else { 140 };
+            Rgba([v, v, v, 255])
+        }));
+        let mut config = Config {
+            resize: false,
+            absolute_offset: false,
+            ascii_ramp: Some("01".to_owned()),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        let size = write_rows(&mut out, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(size, (5, 2));
+        assert_eq!(String::from_utf8(out).unwrap(), "00011\n11111\n");
+
+        // an empty ramp stands for the default one
+        config.ascii_ramp = Some(String::new());
+        let mut out = Vec::new();
+        write_rows(&mut out, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), " :=#@\n+++++\n");
+    }
+}
@@ -1,153 +1,285 @@
+use crate::compose::{linear_to_srgb, srgb_to_linear};
+use crate::config::BlockStyle;
 use crate::error::{ViuError, ViuResult};
 use crate::printer::Printer;
 use crate::Config;
 
-use ansi_colours::ansi256_from_rgb;
+use ansi_colours::{ansi256_from_rgb, rgb_from_ansi256};
 use image::{DynamicImage, GenericImageView, Rgba};
-use std::io::Write;
-use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use std::collections::HashMap;
+use termcolor::{Buffer, BufferWriter, Color, ColorSpec, WriteColor};
 
-use crossterm::cursor::{MoveRight, MoveTo, MoveToPreviousLine};
+use crossterm::cursor::MoveRight;
 use crossterm::execute;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 const UPPER_HALF_BLOCK: &str = "\u{2580}";
 const LOWER_HALF_BLOCK: &str = "\u{2584}";
+const FULL_BLOCK: &str = "\u{2588}";
 
 const CHECKERBOARD_BACKGROUND_LIGHT: (u8, u8, u8) = (153, 153, 153);
 const CHECKERBOARD_BACKGROUND_DARK: (u8, u8, u8) = (102, 102, 102);
 
+// Memoized results of ansi256_from_rgb. Photos repeat the same colors a lot, so every print
+// keeps its own cache, which is dropped with it
+pub(super) type Ansi256Cache = HashMap<(u8, u8, u8), u8>;
+
 pub struct BlockPrinter {}
 
 impl Printer for BlockPrinter {
     fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-        // there are two types of buffers in this function:
-        // - stdout: Buffer, which is from termcolor crate. Used to buffer all writing
-        //   required to print a single image or frame. Flushed on every line
-        // - row_buffer: Vec<ColorSpec>, which stores back- and foreground colors for a
-        //   row of terminal cells. When flushed, its output goes into out_buffer.
-        // They are both flushed on every terminal line (i.e 2 pixel rows)
-        let stdout = BufferWriter::stdout(ColorChoice::Always);
-        let mut out_buffer = stdout.buffer();
-
-        // adjust y offset
-        if config.absolute_offset {
-            if config.y >= 0 {
-                // If absolute_offset, move to (0,y).
-                execute!(out_buffer, MoveTo(0, config.y as u16))?;
-            } else {
-                //Negative values do not make sense.
-                return Err(ViuError::InvalidConfiguration(
-                    "absolute_offset is true but y offset is negative".to_owned(),
-                ));
-            }
-        } else if config.y < 0 {
-            // MoveUp if negative
-            execute!(out_buffer, MoveToPreviousLine(-config.y as u16))?;
-        } else {
-            // Move down y lines
-            for _ in 0..config.y {
-                // writeln! is used instead of MoveDown to force scrolldown
-                // observed when config.y > 0 and cursor is on the last terminal line
-                writeln!(out_buffer)?;
-            }
+        print_rows(img, config, || Ok(()))
+    }
+}
+
+/// Print an image with half blocks to `writer` instead of stdout, for example to capture the
+/// escape codes in a [termcolor::Buffer] or to send them to a pty. Returns the dimensions of
+/// the printed image in terminal cells.
+///
+/// The output is the same as with the block printer, except that the terminal is never asked
+/// for the cursor position. A negative, relative y offset always moves up by its full value.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_to, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let mut buffer = termcolor::Buffer::ansi();
+/// print_to(&mut buffer, &img, &Config::default()).expect("Image printing failed.");
+/// std::fs::write("img.ansi", buffer.into_inner()).expect("Could not write file.");
+/// ```
+pub fn print_to<W: WriteColor>(
+    writer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    write_rows(writer, img, config, false, |_| Ok(()))
+}
+
+/// Render an image with half blocks and return the escape codes as a string, without writing
+/// to or querying the terminal. Useful for snapshot tests or to embed the image in a document.
+///
+/// The output is the same as the one of [print_to] with an ANSI [termcolor::Buffer].
+/// ## Example
+/// ```no_run
+/// use viuer::{render, Config};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let ansi = render(&img, &Config::default()).expect("Image rendering failed.");
+/// print!("{}", ansi);
+/// ```
+pub fn render(img: &DynamicImage, config: &Config) -> ViuResult<String> {
+    let mut buffer = Buffer::ansi();
+    print_to(&mut buffer, img, config)?;
+    String::from_utf8(buffer.into_inner())
+        .map_err(|e| ViuError::IO(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+// Print the image, calling `after_row` every time a full terminal row has been written out
+pub(crate) fn print_rows(
+    img: &DynamicImage,
+    config: &Config,
+    after_row: impl FnMut() -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    // out_buffer is a Buffer from the termcolor crate. Used to buffer all writing
+    // required to print a single image or frame
+    let stdout = super::color_stdout(config)?;
+    let mut out_buffer = stdout.buffer();
+    write_in_batches(
+        &mut out_buffer,
+        img,
+        config,
+        |out_buffer| print_buffer(&stdout, out_buffer),
+        after_row,
+    )
+}
+
+// Write the image to out_buffer, calling `flush` every max_in_flight_rows terminal rows and
+// once more at the end. `flush` is expected to empty the buffer
+pub(crate) fn write_in_batches<W: WriteColor>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    mut flush: impl FnMut(&mut W) -> ViuResult,
+    mut after_row: impl FnMut() -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    let flush_every = config.max_in_flight_rows.unwrap_or(1).max(1);
+    let mut in_flight = 0;
+    let size = write_rows(out_buffer, img, config, true, |out_buffer| {
+        // every row ends with a color reset, so the buffer can be written out after any of them
+        in_flight += 1;
+        if in_flight == flush_every {
+            flush(out_buffer)?;
+            in_flight = 0;
         }
+        after_row()
+    })?;
 
-        // resize the image so that it fits in the constraints, if any
-        let resized_img;
-        let img = if config.resize {
-            resized_img = super::resize(&img, config.width, config.height);
-            &resized_img
-        } else {
-            img
-        };
+    // do a final write to stdout to print last row if length is odd, and reset cursor position
+    flush(out_buffer)?;
+    Ok(size)
+}
 
-        let (width, _) = img.dimensions();
-
-        // TODO: position information is contained in the pixel
-        let mut curr_col_px = 0;
-        let mut curr_row_px = 0;
-
-        let mut row_buffer: Vec<ColorSpec> = Vec::with_capacity(width as usize);
-
-        // row_buffer building mode. At first the top colors are calculated and then the bottom
-        // Once the bottom row is ready, row_buffer is flushed
-        let mut mode = Mode::Top;
-
-        // iterate pixels and fill row_buffer
-        for pixel in img.pixels() {
-            // if the alpha of the pixel is 0, print a predefined pixel based on the position in order
-            // to mimic the checherboard background. If the transparent option was given, move right instead
-            let color = if is_pixel_transparent(pixel) {
-                if config.transparent {
-                    None
-                } else {
-                    Some(get_transparency_color(
-                        curr_row_px,
-                        curr_col_px,
-                        config.truecolor,
-                    ))
-                }
-            } else {
-                Some(get_color_from_pixel(pixel, config.truecolor))
-            };
+// Write the image to out_buffer, calling `row_done` every time a full terminal row has been
+// written to it. The terminal is only queried for the cursor position if `query_cursor` is set.
+fn write_rows<W: WriteColor>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    query_cursor: bool,
+    row_done: impl FnMut(&mut W) -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    // there are two types of buffers in this function:
+    // - out_buffer: receives all writing required to print a single image or frame
+    // - row_buffer: Vec<ColorSpec>, which stores back- and foreground colors for a
+    //   row of terminal cells. When flushed, its output goes into out_buffer.
+    // row_buffer is flushed on every terminal line (i.e 2 pixel rows)
 
-            if mode == Mode::Top {
-                // add a new ColorSpec to row_buffer
-                let mut c = ColorSpec::new();
-                c.set_bg(color);
-                row_buffer.push(c);
-            } else {
-                // upgrade an already existing ColorSpec
-                let colorspec_to_upg = &mut row_buffer[curr_col_px as usize];
-                colorspec_to_upg.set_fg(color);
+    let resolved = super::resolve_truecolor(config);
+    let config: &Config = &resolved;
+    let blends = config.opacity < 1.0 || config.alpha_blend;
+    if config.transparent && config.transparent_bg.is_none() && blends {
+        // the terminal's background color is not known, so there is nothing to blend with
+        return Err(ViuError::InvalidConfiguration(
+            "opacity and alpha_blend need transparent_bg when transparent is set".to_owned(),
+        ));
+    }
+
+    // adjust y offset. The x offset is applied to every row separately
+    super::move_to_first_line(out_buffer, config, query_cursor)?;
+
+    // a cell holds two pixels, one above the other, unless only its background is used
+    let px_per_row = match config.block_style {
+        BlockStyle::HalfBlock => 2,
+        BlockStyle::SpaceBackground => 1,
+    };
+    let prepared = super::prepare_image(img, config, (1, px_per_row));
+    let img: &DynamicImage = &prepared;
+
+    let (width, height) = img.dimensions();
+    let x = super::left_margin(width, config);
+
+    // error diffusion only makes sense for the 256 color palette
+    let mut dither = if config.dither && !config.truecolor_enabled() && !config.force_ansi_grayscale
+    {
+        Some(Dither::new(width))
+    } else {
+        None
+    };
+
+    // grayscale images are read directly instead of expanding every pixel to RGBA
+    let mut cache = Ansi256Cache::new();
+    let colors: Box<dyn Iterator<Item = Option<Color>>> = match img {
+        DynamicImage::ImageLuma8(luma) if dither.is_none() => {
+            Box::new(luma.enumerate_pixels().map(move |(x, y, p)| {
+                let l = p[0];
+                let rgb = adjust_rgb((l, l, l), (x, y), (width, height), config);
+                Some(quantize(rgb, config, &mut cache))
+            }))
+        }
+        // without error diffusion every pixel is independent of the others
+        #[cfg(feature = "rayon")]
+        _ if dither.is_none() => Box::new(par_pixel_colors(img, config).into_iter()),
+        _ => Box::new(img.pixels().map(move |pixel| match dither.as_mut() {
+            Some(dither) if !is_pixel_transparent(pixel, config.alpha_threshold) => {
+                let (x, y, data) = pixel;
+                let rgb = adjust_rgb(
+                    blend_alpha(data, x, y, config),
+                    (x, y),
+                    (width, height),
+                    config,
+                );
+                Some(dither.quantize(x, y, rgb))
             }
+            _ => pixel_color(pixel, (width, height), config, &mut cache),
+        })),
+    };
 
-            curr_col_px += 1;
-            // if the buffer is full start adding the second row of pixels
-            if row_buffer.len() == width as usize {
-                if mode == Mode::Top {
-                    mode = Mode::Bottom;
-                    curr_col_px = 0;
-                    curr_row_px += 1;
-                }
-                // only if the second row is completed, flush the buffer and start again
-                else if curr_col_px == width {
-                    curr_col_px = 0;
-                    curr_row_px += 1;
-
-                    // move right if x offset is specified
-                    if config.x > 0 {
-                        execute!(out_buffer, MoveRight(config.x))?;
-                    }
+    write_colors(out_buffer, colors, width, x, config, row_done)
+}
 
-                    // flush the row_buffer into out_buffer
-                    fill_out_buffer(&mut row_buffer, &mut out_buffer, false)?;
+// Serialize colors, given in row-major order, into out_buffer. Two rows of pixels make up a
+// terminal row, which is moved right by x columns. Returns the size of the output in cells
+fn write_colors<W: WriteColor>(
+    out_buffer: &mut W,
+    colors: impl Iterator<Item = Option<Color>>,
+    width: u32,
+    x: u16,
+    config: &Config,
+    mut row_done: impl FnMut(&mut W) -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    // TODO: position information is contained in the pixel
+    let mut curr_col_px = 0;
+    let mut curr_row_px: u32 = 0;
+
+    let mut row_buffer: Vec<ColorSpec> = Vec::with_capacity(width as usize);
+
+    // row_buffer building mode. At first the top colors are calculated and then the bottom
+    // Once the bottom row is ready, row_buffer is flushed
+    let mut mode = Mode::Top;
+
+    // iterate colors and fill row_buffer
+    for color in colors {
+        if mode == Mode::Top {
+            // add a new ColorSpec to row_buffer
+            let mut c = ColorSpec::new();
+            c.set_bg(color);
+            row_buffer.push(c);
+        } else {
+            // upgrade an already existing ColorSpec
+            let colorspec_to_upg = &mut row_buffer[curr_col_px as usize];
+            colorspec_to_upg.set_fg(color);
+        }
 
-                    // write the line to stdout
-                    print_buffer(&stdout, &mut out_buffer)?;
+        curr_col_px += 1;
+        // if the buffer is full start adding the second row of pixels
+        if row_buffer.len() == width as usize {
+            // with space backgrounds every row of pixels is a terminal row already
+            if mode == Mode::Top && config.block_style == BlockStyle::HalfBlock {
+                mode = Mode::Bottom;
+                curr_col_px = 0;
+                curr_row_px += 1;
+            }
+            // only if the row is completed, flush the buffer and start again
+            else if curr_col_px == width {
+                curr_col_px = 0;
+                curr_row_px += 1;
 
-                    mode = Mode::Top;
-                } else {
-                    // in the middle of the second row, more iterations are required
+                // move right if x offset is specified
+                if x > 0 {
+                    execute!(out_buffer, MoveRight(x))?;
                 }
+
+                // flush the row_buffer into out_buffer
+                fill_out_buffer(&mut row_buffer, out_buffer, false, config)?;
+
+                row_done(out_buffer)?;
+
+                mode = Mode::Top;
+            } else {
+                // in the middle of the second row, more iterations are required
             }
         }
+    }
 
-        // buffer will be flushed if the image has an odd height
-        if !row_buffer.is_empty() {
-            fill_out_buffer(&mut row_buffer, &mut out_buffer, true)?;
+    // buffer will be flushed if the image has an odd height
+    if !row_buffer.is_empty() {
+        if x > 0 {
+            execute!(out_buffer, MoveRight(x))?;
         }
+        fill_out_buffer(&mut row_buffer, out_buffer, true, config)?;
+    }
 
-        // do a final write to stdout to print last row if length is odd, and reset cursor position
-        print_buffer(&stdout, &mut out_buffer)?;
+    // leave the terminal in its default colors, whichever way the image ended
+    out_buffer.reset()?;
 
-        // TODO: might be +1/2 ?
-        Ok((width, curr_row_px / 2))
+    match config.block_style {
+        // the last row of an odd height counts as a whole terminal row
+        BlockStyle::HalfBlock => Ok((width, curr_row_px.div_ceil(2))),
+        BlockStyle::SpaceBackground => Ok((width, curr_row_px)),
     }
 }
 
 // Send out_buffer to stdout. Empties it when it's done
-fn print_buffer(stdout: &BufferWriter, out_buffer: &mut Buffer) -> ViuResult {
+pub(crate) fn print_buffer(stdout: &BufferWriter, out_buffer: &mut Buffer) -> ViuResult {
     match stdout.print(out_buffer) {
         Ok(_) => {
             out_buffer.clear();
@@ -165,17 +297,28 @@ fn print_buffer(stdout: &BufferWriter, out_buffer: &mut Buffer) -> ViuResult {
 // Translates the row_buffer, containing colors, into the out_buffer which will be flushed to the terminal
 fn fill_out_buffer(
     row_buffer: &mut Vec<ColorSpec>,
-    out_buffer: &mut Buffer,
+    out_buffer: &mut impl WriteColor,
     is_last_row: bool,
+    config: &Config,
 ) -> ViuResult {
     let mut out_color;
     let mut out_char;
     let mut new_color;
+    let mut cache = Ansi256Cache::new();
 
     for c in row_buffer.iter() {
+        if config.block_style == BlockStyle::SpaceBackground {
+            // a single pixel, stored as the background color
+            if c.bg().is_none() {
+                execute!(out_buffer, MoveRight(1))?;
+                continue;
+            }
+            out_color = c;
+            out_char = " ";
+        }
         // If a flush is needed it means that only one row with UPPER_HALF_BLOCK must be printed
         // because it is the last row, hence it contains only 1 pixel
-        if is_last_row {
+        else if is_last_row {
             new_color = ColorSpec::new();
             if let Some(bg) = c.bg() {
                 new_color.set_fg(Some(*bg));
@@ -206,10 +349,24 @@ fn fill_out_buffer(
                     out_color = &new_color;
                     out_char = UPPER_HALF_BLOCK;
                 }
-                (Some(_top), Some(_bottom)) => {
-                    // both parts have a color
-                    out_color = c;
-                    out_char = LOWER_HALF_BLOCK;
+                (Some(bottom), Some(top)) => {
+                    // both parts have a color. If they are close enough, print a single full
+                    // block of their average to avoid a visible seam between the halves
+                    match config
+                        .merge_similar_halves
+                        .and_then(|t| merge_colors(*top, *bottom, t))
+                    {
+                        Some(merged) => {
+                            new_color = ColorSpec::new();
+                            new_color.set_fg(Some(quantize(merged, config, &mut cache)));
+                            out_color = &new_color;
+                            out_char = FULL_BLOCK;
+                        }
+                        None => {
+                            out_color = c;
+                            out_char = LOWER_HALF_BLOCK;
+                        }
+                    }
                 }
             }
         }
@@ -224,35 +381,348 @@ fn fill_out_buffer(
     Ok(())
 }
 
-fn is_pixel_transparent(pixel: (u32, u32, Rgba<u8>)) -> bool {
+// Average two colors if none of their channels differ by more than the threshold
+fn merge_colors(top: Color, bottom: Color, threshold: u8) -> Option<(u8, u8, u8)> {
+    let top_rgb = color_to_rgb(top)?;
+    let bottom_rgb = color_to_rgb(bottom)?;
+
+    let channels = [
+        (top_rgb.0, bottom_rgb.0),
+        (top_rgb.1, bottom_rgb.1),
+        (top_rgb.2, bottom_rgb.2),
+    ];
+    if channels
+        .iter()
+        .any(|(t, b)| (*t as i16 - *b as i16).unsigned_abs() > threshold as u16)
+    {
+        return None;
+    }
+
+    let avg = |(t, b): (u8, u8)| ((t as u16 + b as u16) / 2) as u8;
+    Some((avg(channels[0]), avg(channels[1]), avg(channels[2])))
+}
+
+fn color_to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Ansi256(i) => Some(rgb_from_ansi256(i)),
+        _ => None,
+    }
+}
+
+fn is_pixel_transparent(pixel: (u32, u32, Rgba<u8>), threshold: u8) -> bool {
     let (_x, _y, data) = pixel;
-    data[3] == 0
+    data[3] <= threshold
+}
+
+// Terminal color of a single pixel, or None if it is transparent and config.transparent is set.
+// Transparent pixels get a predefined color based on their position in order to mimic the
+// checkerboard background
+fn pixel_color(
+    pixel: (u32, u32, Rgba<u8>),
+    dimensions: (u32, u32),
+    config: &Config,
+    cache: &mut Ansi256Cache,
+) -> Option<Color> {
+    if is_pixel_transparent(pixel, config.alpha_threshold) {
+        if config.transparent {
+            None
+        } else {
+            Some(get_checkerboard_color(pixel, config, cache))
+        }
+    } else {
+        Some(get_color_from_pixel(pixel, dimensions, config, cache))
+    }
+}
+
+// Same as mapping pixel_color over img.pixels(), but the rows are converted in parallel. Each
+// batch of rows handled by a thread gets a cache of its own
+#[cfg(feature = "rayon")]
+fn par_pixel_colors(img: &DynamicImage, config: &Config) -> Vec<Option<Color>> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut colors = vec![None; (width * height) as usize];
+    if width == 0 {
+        return colors;
+    }
+    colors
+        .par_chunks_mut(width as usize)
+        .zip(rgba.as_raw().par_chunks(4 * width as usize))
+        .enumerate()
+        .for_each_init(Ansi256Cache::new, |cache, (y, (row, data))| {
+            for (x, (color, px)) in row.iter_mut().zip(data.chunks_exact(4)).enumerate() {
+                let pixel = (x as u32, y as u32, Rgba([px[0], px[1], px[2], px[3]]));
+                *color = pixel_color(pixel, (width, height), config, cache);
+            }
+        });
+    colors
+}
+
+// Color of the pixel after the adjustments from the config, or None if it is transparent and
+// true transparency is enabled. Other transparent pixels show the transparency background.
+pub(super) fn pixel_rgb(
+    pixel: (u32, u32, Rgba<u8>),
+    dimensions: (u32, u32),
+    config: &Config,
+) -> Option<(u8, u8, u8)> {
+    let (x, y, data) = pixel;
+    if is_pixel_transparent(pixel, config.alpha_threshold) {
+        if config.transparent {
+            None
+        } else {
+            Some(shown_transparency_rgb(x, y, config))
+        }
+    } else {
+        Some(adjust_rgb(
+            blend_alpha(data, x, y, config),
+            (x, y),
+            dimensions,
+            config,
+        ))
+    }
 }
 
-fn get_transparency_color(row: u32, col: u32, truecolor: bool) -> Color {
+// Color of a transparent pixel: the checkerboard, aligned to config.checkerboard_origin, or
+// config.transparent_bg if it is set
+fn get_checkerboard_color(
+    pixel: (u32, u32, Rgba<u8>),
+    config: &Config,
+    cache: &mut Ansi256Cache,
+) -> Color {
+    let (x, y, _data) = pixel;
+    quantize(shown_transparency_rgb(x, y, config), config, cache)
+}
+
+fn transparency_rgb(x: u32, y: u32, config: &Config) -> (u8, u8, u8) {
+    match config.transparent_bg {
+        Some(rgb) => rgb,
+        None => {
+            let (origin_x, origin_y) = config.checkerboard_origin;
+            checkerboard_rgb(y + origin_y, x + origin_x)
+        }
+    }
+}
+
+// Color printed for a transparent pixel, inverted along with the image
+fn shown_transparency_rgb(x: u32, y: u32, config: &Config) -> (u8, u8, u8) {
+    let rgb = transparency_rgb(x, y, config);
+    if config.invert {
+        invert_rgb(rgb)
+    } else {
+        rgb
+    }
+}
+
+fn checkerboard_rgb(row: u32, col: u32) -> (u8, u8, u8) {
     //imitate the transparent chess board pattern
-    let rgb = if row % 2 == col % 2 {
+    if row % 2 == col % 2 {
         CHECKERBOARD_BACKGROUND_DARK
     } else {
         CHECKERBOARD_BACKGROUND_LIGHT
-    };
-    if truecolor {
-        Color::Rgb(rgb.0, rgb.1, rgb.2)
+    }
+}
+
+// Color drawn behind the pixel at (x, y). With true transparency the terminal's background
+// shows through, which is unknown, so printing requires transparent_bg to stand in for it.
+fn background_rgb(x: u32, y: u32, config: &Config) -> (u8, u8, u8) {
+    if config.transparent {
+        config.transparent_bg.unwrap_or((0, 0, 0))
     } else {
-        Color::Ansi256(ansi256_from_rgb(rgb))
+        transparency_rgb(x, y, config)
     }
 }
 
-fn get_color_from_pixel(pixel: (u32, u32, Rgba<u8>), truecolor: bool) -> Color {
-    let (_x, _y, data) = pixel;
+fn get_color_from_pixel(
+    pixel: (u32, u32, Rgba<u8>),
+    dimensions: (u32, u32),
+    config: &Config,
+    cache: &mut Ansi256Cache,
+) -> Color {
+    let (x, y, data) = pixel;
+    let rgb = adjust_rgb(blend_alpha(data, x, y, config), (x, y), dimensions, config);
+    quantize(rgb, config, cache)
+}
+
+// Color of the pixel at (x, y). With config.alpha_blend, a partially transparent pixel is
+// composited over the background behind it, in linear light if config.linear_compositing is
+// set. Otherwise, its alpha is ignored.
+fn blend_alpha(data: Rgba<u8>, x: u32, y: u32, config: &Config) -> (u8, u8, u8) {
     let rgb = (data[0], data[1], data[2]);
-    if truecolor {
+    if !config.alpha_blend || data[3] == 255 {
+        return rgb;
+    }
+
+    let alpha = data[3] as f32 / 255.0;
+    let bg = background_rgb(x, y, config);
+    if config.linear_compositing {
+        let blend = |fg: u8, bg: u8| {
+            let (fg, bg) = (srgb_to_linear(fg), srgb_to_linear(bg));
+            linear_to_srgb(fg * alpha + bg * (1.0 - alpha))
+        };
+        (blend(rgb.0, bg.0), blend(rgb.1, bg.1), blend(rgb.2, bg.2))
+    } else {
+        mix_rgb(bg, rgb, alpha)
+    }
+}
+
+// Pick the terminal color for the adjusted pixel, as allowed by the config
+pub(super) fn quantize(rgb: (u8, u8, u8), config: &Config, cache: &mut Ansi256Cache) -> Color {
+    if config.force_ansi_grayscale {
+        Color::Ansi256(ansi_grey_ramp(luminance(rgb)))
+    } else if config.truecolor_enabled() {
         Color::Rgb(rgb.0, rgb.1, rgb.2)
     } else {
-        Color::Ansi256(ansi256_from_rgb(rgb))
+        Color::Ansi256(cached_ansi256(rgb, cache))
     }
 }
 
+// Closest step of the 24 grays at the end of the 256 color palette (232-255), which go from
+// 8 to 238 in steps of 10
+fn ansi_grey_ramp(luma: u8) -> u8 {
+    let step = ((luma as f32 - 8.0) / 10.0).round().clamp(0.0, 23.0);
+    232 + step as u8
+}
+
+pub(super) fn luminance((r, g, b): (u8, u8, u8)) -> u8 {
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000) as u8
+}
+
+fn cached_ansi256(rgb: (u8, u8, u8), cache: &mut Ansi256Cache) -> u8 {
+    *cache.entry(rgb).or_insert_with(|| ansi256_from_rgb(rgb))
+}
+
+// Apply the color adjustments from the config to the pixel at `position`, before it is
+// quantized to a terminal color
+fn adjust_rgb(
+    mut rgb: (u8, u8, u8),
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    config: &Config,
+) -> (u8, u8, u8) {
+    if let Some(matrix) = &config.color_matrix {
+        rgb = apply_color_matrix(matrix, rgb);
+    }
+    if let Some(strength) = config.vignette {
+        let factor = vignette_factor(x, y, width, height, strength);
+        rgb = (
+            scale_channel(rgb.0, factor),
+            scale_channel(rgb.1, factor),
+            scale_channel(rgb.2, factor),
+        );
+    }
+    if config.opacity < 1.0 {
+        rgb = mix_rgb(background_rgb(x, y, config), rgb, config.opacity);
+    }
+    if config.grayscale {
+        let luma = luminance(rgb);
+        rgb = (luma, luma, luma);
+    }
+    if config.invert {
+        rgb = invert_rgb(rgb);
+    }
+    rgb
+}
+
+fn invert_rgb((r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+    (255 - r, 255 - g, 255 - b)
+}
+
+// Multiply the (r, g, b) column vector by the matrix, clamping every channel to 0-255
+fn apply_color_matrix(matrix: &[[f32; 3]; 3], (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let channel = |row: &[f32; 3]| {
+        (row[0] * r + row[1] * g + row[2] * b)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    (
+        channel(&matrix[0]),
+        channel(&matrix[1]),
+        channel(&matrix[2]),
+    )
+}
+
+// Floyd-Steinberg error diffusion to the 256 color palette. Pixels must be quantized in
+// row-major order, so that the error of each one is carried to its neighbors that come later.
+struct Dither {
+    row: u32,
+    // error carried to the pixels of the current and the next row
+    current: Vec<[f32; 3]>,
+    next: Vec<[f32; 3]>,
+}
+
+impl Dither {
+    fn new(width: u32) -> Self {
+        Self {
+            row: 0,
+            current: vec![[0.0; 3]; width as usize],
+            next: vec![[0.0; 3]; width as usize],
+        }
+    }
+
+    fn quantize(&mut self, x: u32, y: u32, (r, g, b): (u8, u8, u8)) -> Color {
+        while self.row < y {
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.next.iter_mut().for_each(|e| *e = [0.0; 3]);
+            self.row += 1;
+        }
+
+        let x = x as usize;
+        let carried = self.current[x];
+        let wanted = [
+            r as f32 + carried[0],
+            g as f32 + carried[1],
+            b as f32 + carried[2],
+        ];
+        let channel = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+        let index = ansi256_from_rgb((channel(wanted[0]), channel(wanted[1]), channel(wanted[2])));
+        let (pr, pg, pb) = rgb_from_ansi256(index);
+        let error = [
+            wanted[0] - pr as f32,
+            wanted[1] - pg as f32,
+            wanted[2] - pb as f32,
+        ];
+
+        let width = self.current.len();
+        let spread = |errors: &mut [[f32; 3]], x: usize, weight: f32| {
+            for (e, error) in errors[x].iter_mut().zip(error.iter()) {
+                *e += error * weight;
+            }
+        };
+        if x + 1 < width {
+            spread(&mut self.current, x + 1, 7.0 / 16.0);
+            spread(&mut self.next, x + 1, 1.0 / 16.0);
+        }
+        if x > 0 {
+            spread(&mut self.next, x - 1, 3.0 / 16.0);
+        }
+        spread(&mut self.next, x, 5.0 / 16.0);
+
+        Color::Ansi256(index)
+    }
+}
+
+// Linear interpolation from `from` (at t = 0) to `to` (at t = 1)
+fn mix_rgb(from: (u8, u8, u8), to: (u8, u8, u8), t: f32) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// How much a pixel is darkened by the vignette. The factor falls off with the squared distance
+// from the center of the image, reaching 1 - strength in the corners.
+fn vignette_factor(x: u32, y: u32, width: u32, height: u32, strength: f32) -> f32 {
+    let dx = (x as f32 + 0.5) / width as f32 - 0.5;
+    let dy = (y as f32 + 0.5) / height as f32 - 0.5;
+    // the distance to a corner is 0.5 in both directions
+    let distance = (dx * dx + dy * dy) / 0.5;
+    (1.0 - strength * distance).clamp(0.0, 1.0)
+}
+
+fn scale_channel(c: u8, factor: f32) -> u8 {
+    (c as f32 * factor).round() as u8
+}
+
 // enum used to keep track where the current line of pixels processed should be displayed - as
 // background or foreground color
 #[derive(PartialEq)]
@@ -282,6 +752,638 @@ mod tests {
         assert_eq!(h, 3);
     }
 
+    #[test]
+    fn test_print_to() {
+        let img =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])));
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            truecolor: Some(true),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::ansi();
+        let (w, h) = print_to(&mut buffer, &img, &config).unwrap();
+        assert_eq!((w, h), (2, 1));
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "\x1b[0G\x1b[0m\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m\u{2584}\x1b[0m\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m\u{2584}\x1b[0m\n\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let img =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(3, 3, Rgba([0, 0, 255, 255])));
+        let config = Config {
+            resize: false,
+            truecolor: Some(false),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::ansi();
+        print_to(&mut buffer, &img, &config).unwrap();
+        let ansi = render(&img, &config).unwrap();
+        assert_eq!(ansi.as_bytes(), buffer.as_slice());
+        // absolute offset, two rows, in 256 colors
+        assert!(ansi.starts_with("\x1b[1;1H"));
+        assert_eq!(ansi.matches('\n').count(), 2);
+        assert!(ansi.contains("\x1b[38;5;21m"));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_colors_match_serial() {
+        // large enough for the rows to be spread over several threads, with an odd height so
+        // that the last row of cells has only its top half
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(640, 361, |x, y| {
+            let a = match (x + y) % 7 {
+                0 => 0,
+                1 | 2 => (x % 256) as u8,
+                _ => 255,
+            };
+            Rgba([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8, a])
+        }));
+        let backgrounds = [
+            Config::default(),
+            Config {
+                transparent: true,
+                ..Default::default()
+            },
+            Config {
+                alpha_blend: true,
+                ..Default::default()
+            },
+            Config {
+                transparent_bg: Some((30, 60, 90)),
+                alpha_blend: true,
+                ..Default::default()
+            },
+            Config {
+                transparent: true,
+                transparent_bg: Some((30, 60, 90)),
+                alpha_blend: true,
+                ..Default::default()
+            },
+        ];
+        let configs = backgrounds.iter().flat_map(|background| {
+            [false, true].iter().map(move |&truecolor| Config {
+                truecolor: Some(truecolor),
+                x: 3,
+                ..background.clone()
+            })
+        });
+        for config in configs {
+            let (width, height) = img.dimensions();
+            let mut cache = Ansi256Cache::new();
+            let serial = img
+                .pixels()
+                .map(|pixel| pixel_color(pixel, (width, height), &config, &mut cache));
+            let parallel = par_pixel_colors(&img, &config).into_iter();
+
+            let mut serial_buffer = Buffer::ansi();
+            let mut parallel_buffer = Buffer::ansi();
+            let serial_size =
+                write_colors(&mut serial_buffer, serial, width, 3, &config, |_| Ok(())).unwrap();
+            let parallel_size = write_colors(
+                &mut parallel_buffer,
+                parallel,
+                width,
+                3,
+                &config,
+                |_| Ok(()),
+            )
+            .unwrap();
+            assert_eq!(serial_size, parallel_size);
+            assert_eq!(serial_buffer.as_slice(), parallel_buffer.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_cached_ansi256() {
+        let mut cache = Ansi256Cache::new();
+        for _ in 0..2 {
+            for r in (0..=255).step_by(15) {
+                for g in (0..=255).step_by(17) {
+                    let rgb = (r, g, 255 - r);
+                    assert_eq!(cached_ansi256(rgb, &mut cache), ansi256_from_rgb(rgb));
+                }
+            }
+        }
+        assert_eq!(cache.len(), 18 * 16);
+    }
+
+    #[test]
+    fn test_block_printer_odd_height() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 5));
+
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            ..Default::default()
+        };
+        let (w, h) = BlockPrinter {}.print(&img, &config).unwrap();
+
+        assert_eq!(w, 20);
+        assert_eq!(h, 3);
+    }
+
+    #[test]
+    fn test_x_offset_odd_height() {
+        let img =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 3, Rgba([0, 255, 0, 255])));
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            x: 4,
+            ..Default::default()
+        };
+        let mut buffer = Buffer::ansi();
+        print_to(&mut buffer, &img, &config).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        // the final reset follows the last line
+        let rows: Vec<&str> = output.strip_suffix("\x1b[0m").unwrap().lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("\x1b[0G\x1b[4C"));
+        assert!(rows[1].starts_with("\x1b[4C"));
+    }
+
+    #[test]
+    fn test_space_background() {
+        let mut img = image::RgbaImage::from_pixel(2, 3, Rgba([0, 0, 255, 255]));
+        img.put_pixel(1, 2, Rgba([0, 0, 0, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            transparent: true,
+            truecolor: Some(true),
+            block_style: BlockStyle::SpaceBackground,
+            ..Default::default()
+        };
+        let mut buffer = Buffer::ansi();
+        let (w, h) = print_to(&mut buffer, &img, &config).unwrap();
+        assert_eq!((w, h), (2, 3));
+
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        let output = output.strip_suffix("\x1b[0m").unwrap();
+        assert_eq!(output.lines().count(), 3);
+        assert_eq!(output.matches("\x1b[48;2;0;0;255m ").count(), 5);
+        assert!(!output.contains(UPPER_HALF_BLOCK) && !output.contains(LOWER_HALF_BLOCK));
+        // the transparent pixel is skipped
+        assert!(output.lines().last().unwrap().contains("\x1b[1C"));
+    }
+
+    #[test]
+    fn test_max_in_flight_rows() {
+        // 8 terminal rows
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(10, 15));
+        for (max, flushes) in [(None, 8), (Some(0), 8), (Some(3), 3), (Some(100), 1)] {
+            let config = Config {
+                resize: false,
+                absolute_offset: false,
+                max_in_flight_rows: max,
+                ..Default::default()
+            };
+            let mut written = Vec::new();
+            let size = write_in_batches(
+                &mut Buffer::ansi(),
+                &img,
+                &config,
+                |buffer| {
+                    if !buffer.is_empty() {
+                        written.push(String::from_utf8(buffer.as_slice().to_vec()).unwrap());
+                    }
+                    buffer.clear();
+                    Ok(())
+                },
+                || Ok(()),
+            )
+            .unwrap();
+            assert_eq!(size, (10, 8));
+            assert_eq!(written.len(), flushes);
+            for batch in &written {
+                // nothing is left colored at a flush
+                assert!(batch.trim_end().ends_with("\x1b[0m"), "{:?}", batch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ends_with_reset() {
+        let even =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(3, 4, Rgba([0, 90, 0, 255])));
+        let odd =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(3, 3, Rgba([90, 0, 0, 255])));
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            ..Default::default()
+        };
+
+        let mut buffer = Buffer::ansi();
+        print_to(&mut buffer, &even, &config).unwrap();
+        print_to(&mut buffer, &odd, &config).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        assert!(output.ends_with("\x1b[0m"));
+
+        // every color is reset before the next image starts
+        for image in output.split("\x1b[0G").filter(|s| !s.is_empty()) {
+            let last_color = image.rfind("\x1b[38;").max(image.rfind("\x1b[48;"));
+            let last_reset = image.rfind("\x1b[0m");
+            assert!(last_color < last_reset);
+            assert!(image.ends_with("\x1b[0m"));
+        }
+    }
+
+    #[test]
+    fn test_print_luma() {
+        // a dark pixel over a light one
+        let img =
+            DynamicImage::ImageLuma8(image::GrayImage::from_raw(1, 2, vec![100, 200]).unwrap());
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            truecolor: Some(true),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::ansi();
+        assert_eq!(print_to(&mut buffer, &img, &config).unwrap(), (1, 1));
+        let out = String::from_utf8(buffer.into_inner()).unwrap();
+        assert!(
+            out.contains("\x1b[38;2;200;200;200m\x1b[48;2;100;100;100m\u{2584}"),
+            "{:?}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_luma_color_matches_rgba() {
+        let luma = DynamicImage::ImageLuma8(image::GrayImage::from_fn(16, 4, |x, y| {
+            image::Luma([(x * 16 + y) as u8])
+        }));
+        let rgba = DynamicImage::ImageRgba8(luma.to_rgba8());
+        let adjustments = [
+            Config::default(),
+            Config {
+                force_ansi_grayscale: true,
+                ..Default::default()
+            },
+            Config {
+                invert: true,
+                opacity: 0.5,
+                vignette: Some(0.5),
+                ..Default::default()
+            },
+        ];
+        for (adjusted, truecolor) in adjustments
+            .iter()
+            .flat_map(|a| [false, true].iter().map(move |&t| (a, t)))
+        {
+            let config = Config {
+                truecolor: Some(truecolor),
+                resize: false,
+                absolute_offset: false,
+                ..adjusted.clone()
+            };
+            let (mut luma_buffer, mut rgba_buffer) = (Buffer::ansi(), Buffer::ansi());
+            print_to(&mut luma_buffer, &luma, &config).unwrap();
+            print_to(&mut rgba_buffer, &rgba, &config).unwrap();
+            assert_eq!(luma_buffer.into_inner(), rgba_buffer.into_inner());
+        }
+    }
+
+    #[test]
+    fn test_force_ansi_grayscale() {
+        assert_eq!(ansi_grey_ramp(0), 232);
+        assert_eq!(ansi_grey_ramp(17), 233);
+        assert_eq!(ansi_grey_ramp(128), 244);
+        assert_eq!(ansi_grey_ramp(255), 255);
+
+        let config = Config {
+            truecolor: Some(true),
+            force_ansi_grayscale: true,
+            ..Default::default()
+        };
+        // green is brighter than blue
+        let green = (0, 0, Rgba([0, 255, 0, 255]));
+        let blue = (0, 0, Rgba([0, 0, 255, 255]));
+        assert_eq!(
+            get_color_from_pixel(green, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Ansi256(246)
+        );
+        assert_eq!(
+            get_color_from_pixel(blue, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Ansi256(234)
+        );
+    }
+
+    #[test]
+    fn test_checkerboard_origin() {
+        let color_at = |x, y, checkerboard_origin| {
+            let config = Config {
+                truecolor: Some(true),
+                checkerboard_origin,
+                ..Default::default()
+            };
+            get_checkerboard_color(
+                (x, y, Rgba([0, 0, 0, 0])),
+                &config,
+                &mut Ansi256Cache::new(),
+            )
+        };
+        // a region cropped at (3, 1) keeps the pattern of the original image
+        assert_eq!(color_at(3, 1, (0, 0)), color_at(0, 0, (3, 1)));
+        assert_eq!(color_at(4, 1, (0, 0)), color_at(1, 0, (3, 1)));
+        assert_ne!(color_at(0, 0, (0, 0)), color_at(0, 0, (1, 0)));
+    }
+
+    #[test]
+    fn test_color_matrix() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(
+            apply_color_matrix(&identity, (12, 140, 255)),
+            (12, 140, 255)
+        );
+
+        // swap red and blue, double green
+        let matrix = [[0.0, 0.0, 1.0], [0.0, 2.0, 0.0], [1.0, 0.0, 0.0]];
+        assert_eq!(apply_color_matrix(&matrix, (12, 140, 255)), (255, 255, 12));
+
+        let negative = [[-1.0, 0.0, 0.0], [0.0, 0.5, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(apply_color_matrix(&negative, (12, 3, 255)), (0, 2, 255));
+
+        let config = Config {
+            truecolor: Some(true),
+            color_matrix: Some(matrix),
+            ..Default::default()
+        };
+        let pixel = (0, 0, Rgba([12, 140, 255, 255]));
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(255, 255, 12)
+        );
+    }
+
+    #[test]
+    fn test_dither() {
+        // a gray between two palette grays alternates between them
+        let mut dither = Dither::new(4);
+        let row: Vec<Color> = (0..4)
+            .map(|x| dither.quantize(x, 0, (13, 13, 13)))
+            .collect();
+        assert_eq!(
+            row,
+            vec![
+                Color::Ansi256(232),
+                Color::Ansi256(233),
+                Color::Ansi256(232),
+                Color::Ansi256(233)
+            ]
+        );
+
+        // palette colors are kept as they are
+        let mut dither = Dither::new(2);
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(dither.quantize(x, y, (255, 0, 0)), Color::Ansi256(196));
+            }
+        }
+    }
+
+    #[test]
+    fn test_transparent_bg() {
+        let pixel = (1, 0, Rgba([0, 0, 0, 0]));
+        let mut config = Config {
+            truecolor: Some(true),
+            transparent_bg: Some((10, 20, 30)),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_checkerboard_color(pixel, &config, &mut Ansi256Cache::new()),
+            Color::Rgb(10, 20, 30)
+        );
+        // half transparent images fade into it
+        config.opacity = 0.5;
+        let red = (0, 0, Rgba([210, 0, 0, 255]));
+        assert_eq!(
+            get_color_from_pixel(red, (2, 2), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(110, 10, 15)
+        );
+
+        config.transparent_bg = None;
+        assert_eq!(
+            get_checkerboard_color(pixel, &config, &mut Ansi256Cache::new()),
+            Color::Rgb(153, 153, 153)
+        );
+    }
+
+    #[test]
+    fn test_alpha_blend() {
+        let mut config = Config {
+            truecolor: Some(true),
+            transparent_bg: Some((0, 0, 200)),
+            ..Default::default()
+        };
+        let pixel = (0, 0, Rgba([200, 100, 0, 128]));
+        // the alpha is ignored by default
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(200, 100, 0)
+        );
+
+        config.alpha_blend = true;
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(100, 50, 100)
+        );
+        config.linear_compositing = true;
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(147, 72, 146)
+        );
+
+        // fully transparent pixels are still handled separately
+        assert!(is_pixel_transparent((0, 0, Rgba([200, 100, 0, 0])), 0));
+    }
+
+    #[test]
+    fn test_alpha_threshold() {
+        let speck = (0, 0, Rgba([200, 100, 0, 8]));
+        assert!(!is_pixel_transparent(speck, 0));
+        assert!(is_pixel_transparent(speck, 8));
+        assert!(!is_pixel_transparent((0, 0, Rgba([200, 100, 0, 9])), 8));
+
+        let mut config = Config {
+            truecolor: Some(true),
+            alpha_threshold: 10,
+            ..Default::default()
+        };
+        let mut cache = Ansi256Cache::new();
+        assert_eq!(
+            pixel_color(speck, (1, 1), &config, &mut cache),
+            Some(get_checkerboard_color(speck, &config, &mut cache))
+        );
+        config.transparent = true;
+        assert_eq!(pixel_color(speck, (1, 1), &config, &mut cache), None);
+        assert_eq!(pixel_rgb(speck, (1, 1), &config), None);
+
+        // true transparency leaves nothing to blend with, unless transparent_bg is set
+        config.alpha_blend = true;
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, Rgba([0; 4])));
+        assert!(matches!(
+            print_to(&mut Buffer::ansi(), &img, &config),
+            Err(ViuError::InvalidConfiguration(_))
+        ));
+        config.transparent_bg = Some((0, 0, 200));
+        assert!(print_to(&mut Buffer::ansi(), &img, &config).is_ok());
+    }
+
+    #[test]
+    fn test_grayscale_invert() {
+        let pixel = (0, 0, Rgba([200, 100, 0, 255]));
+        let mut config = Config {
+            truecolor: Some(true),
+            grayscale: true,
+            ..Default::default()
+        };
+        let mut cache = Ansi256Cache::new();
+        // 0.299 * 200 + 0.587 * 100 = 118.5
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut cache),
+            Color::Rgb(119, 119, 119)
+        );
+        config.invert = true;
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut cache),
+            Color::Rgb(136, 136, 136)
+        );
+        config.grayscale = false;
+        assert_eq!(
+            get_color_from_pixel(pixel, (1, 1), &config, &mut cache),
+            Color::Rgb(55, 155, 255)
+        );
+
+        // only inversion changes the checkerboard
+        let transparent = (0, 0, Rgba([0, 0, 0, 0]));
+        assert_eq!(
+            get_checkerboard_color(transparent, &config, &mut cache),
+            Color::Rgb(153, 153, 153)
+        );
+        config.invert = false;
+        config.grayscale = true;
+        assert_eq!(
+            get_checkerboard_color(transparent, &config, &mut cache),
+            Color::Rgb(102, 102, 102)
+        );
+    }
+
+    #[test]
+    fn test_opacity() {
+        let mut config = Config {
+            truecolor: Some(true),
+            opacity: 0.5,
+            ..Default::default()
+        };
+        // the dark checkerboard square is behind (0, 0)
+        let pixel = (0, 0, Rgba([202, 2, 102, 255]));
+        assert_eq!(
+            get_color_from_pixel(pixel, (2, 2), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(152, 52, 102)
+        );
+
+        // with true transparency, the image fades into transparent_bg
+        config.transparent = true;
+        config.transparent_bg = Some((2, 202, 0));
+        assert_eq!(
+            get_color_from_pixel(pixel, (2, 2), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(102, 102, 51)
+        );
+        // and can't fade without it
+        config.transparent_bg = None;
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, Rgba([0; 4])));
+        assert!(matches!(
+            print_to(&mut Buffer::ansi(), &img, &config),
+            Err(ViuError::InvalidConfiguration(_))
+        ));
+
+        config.opacity = 1.0;
+        assert_eq!(
+            get_color_from_pixel(pixel, (2, 2), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(202, 2, 102)
+        );
+    }
+
+    #[test]
+    fn test_vignette() {
+        // the center is left alone, corners get darker as the strength grows
+        assert!((vignette_factor(50, 50, 101, 101, 1.0) - 1.0).abs() < 1e-6);
+        assert!(vignette_factor(0, 0, 101, 101, 0.5) > 0.5);
+        assert!(vignette_factor(0, 0, 101, 101, 1.0) < 0.05);
+        assert!((vignette_factor(0, 0, 101, 101, 0.0) - 1.0).abs() < 1e-6);
+
+        let config = Config {
+            truecolor: Some(true),
+            vignette: Some(1.0),
+            ..Default::default()
+        };
+        let pixel = (0, 0, Rgba([200, 200, 200, 255]));
+        assert_eq!(
+            get_color_from_pixel(pixel, (1000, 1000), &config, &mut Ansi256Cache::new()),
+            Color::Rgb(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_merge_colors() {
+        let top = Color::Rgb(100, 100, 100);
+        let bottom = Color::Rgb(104, 98, 100);
+        assert_eq!(merge_colors(top, bottom, 4), Some((102, 99, 100)));
+        assert_eq!(merge_colors(top, bottom, 3), None);
+
+        let same = Color::Ansi256(196);
+        assert_eq!(merge_colors(same, same, 0), Some((255, 0, 0)));
+    }
+
+    #[test]
+    fn test_fill_out_buffer_merges_halves() {
+        let mut c = ColorSpec::new();
+        c.set_bg(Some(Color::Rgb(10, 10, 10)));
+        c.set_fg(Some(Color::Rgb(12, 12, 12)));
+
+        let mut config = Config {
+            merge_similar_halves: Some(2),
+            ..Default::default()
+        };
+        let mut out_buffer = Buffer::ansi();
+        fill_out_buffer(&mut vec![c.clone()], &mut out_buffer, false, &config).unwrap();
+        let out = String::from_utf8(out_buffer.into_inner()).unwrap();
+        assert!(out.contains(FULL_BLOCK));
+
+        config.merge_similar_halves = None;
+        let mut out_buffer = Buffer::ansi();
+        fill_out_buffer(&mut vec![c], &mut out_buffer, false, &config).unwrap();
+        let out = String::from_utf8(out_buffer.into_inner()).unwrap();
+        assert!(out.contains(LOWER_HALF_BLOCK));
+    }
+
+    #[test]
+    fn test_merged_halves_stay_gray() {
+        // grays 88 and 98 of the palette, whose average is closest to the (95, 95, 95) of the
+        // color cube
+        let mut c = ColorSpec::new();
+        c.set_bg(Some(Color::Ansi256(240)));
+        c.set_fg(Some(Color::Ansi256(241)));
+        let config = Config {
+            merge_similar_halves: Some(20),
+            force_ansi_grayscale: true,
+            ..Default::default()
+        };
+        let mut out_buffer = Buffer::ansi();
+        fill_out_buffer(&mut vec![c], &mut out_buffer, false, &config).unwrap();
+        let out = String::from_utf8(out_buffer.into_inner()).unwrap();
+        assert!(out.contains("\x1b[38;5;241m\u{2588}"), "{:?}", out);
+    }
+
     // TODO: failing on Windows. Why?
     #[test]
     fn test_block_printer_large() {
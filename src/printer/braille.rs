@@ -0,0 +1,173 @@
+use crate::error::ViuResult;
+use crate::printer::block::{luminance, pixel_rgb, print_buffer, quantize, Ansi256Cache};
+use crate::printer::Printer;
+use crate::Config;
+
+use crossterm::cursor::MoveRight;
+use crossterm::execute;
+use image::{DynamicImage, GenericImageView};
+use termcolor::{BufferWriter, ColorSpec, WriteColor};
+
+// Braille pattern with no dots raised. The other patterns follow it, one bit per dot
+const BLANK_BRAILLE: u32 = 0x2800;
+
+// Bit of every dot in a braille pattern, indexed by row and then column
+const DOT_BITS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub struct BraillePrinter {}
+
+impl Printer for BraillePrinter {
+    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+        let stdout = BufferWriter::stdout(config.resolved_color_choice());
+        let mut out_buffer = stdout.buffer();
+        write_rows(&mut out_buffer, img, config, true, |out_buffer| {
+            print_buffer(&stdout, out_buffer)
+        })
+    }
+}
+
+// Write the image to out_buffer, calling `row_done` after every terminal row. The terminal
+// is only queried for the cursor position if `query_cursor` is set.
+fn write_rows<W: WriteColor>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    query_cursor: bool,
+    mut row_done: impl FnMut(&mut W) -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    let resolved = super::resolve_truecolor(config);
+    let config: &Config = &resolved;
+
+    super::move_to_first_line(out_buffer, config, query_cursor)?;
+
+    // a cell holds 2x4 dots
+    let prepared = super::prepare_image(img, config, (2, 4));
+    let img: &DynamicImage = &prepared;
+    let (width, height) = img.dimensions();
+    let (cols, rows) = (width.div_ceil(2), height.div_ceil(4));
+    let x = super::left_margin(cols, config);
+
+    let mut color = ColorSpec::new();
+    color.set_fg(
+        config
+            .braille_color
+            .map(|rgb| quantize(rgb, config, &mut Ansi256Cache::new())),
+    );
+
+    for row in 0..rows {
+        if x > 0 {
+            execute!(out_buffer, MoveRight(x))?;
+        }
+        out_buffer.set_color(&color)?;
+        for col in 0..cols {
+            let pattern = cell_pattern(img, col, row, config);
+            if pattern == 0 && config.transparent {
+                // nothing to draw, let the background show through
+                execute!(out_buffer, MoveRight(1))?;
+            } else {
+                write!(out_buffer, "{}", braille(pattern))?;
+            }
+        }
+        out_buffer.reset()?;
+        writeln!(out_buffer)?;
+        row_done(out_buffer)?;
+    }
+
+    Ok((cols, rows))
+}
+
+// Raised dots of the cell at (col, row), as the bits of its braille pattern. A dot is raised if
+// the luma of its pixel reaches config.braille_threshold. Transparent pixels and the ones past
+// the edges of the image are never raised.
+fn cell_pattern(img: &DynamicImage, col: u32, row: u32, config: &Config) -> u8 {
+    let (width, height) = img.dimensions();
+    let mut pattern = 0;
+    for (dy, bits) in DOT_BITS.iter().enumerate() {
+        for (dx, bit) in bits.iter().enumerate() {
+            let x = 2 * col + dx as u32;
+            let y = 4 * row + dy as u32;
+            if x >= width || y >= height {
+                continue;
+            }
+            let pixel = pixel_rgb((x, y, img.get_pixel(x, y)), (width, height), config);
+            if pixel.is_some_and(|rgb| luminance(rgb) >= config.braille_threshold) {
+                pattern |= bit;
+            }
+        }
+    }
+    pattern
+}
+
+fn braille(pattern: u8) -> char {
+    std::char::from_u32(BLANK_BRAILLE + pattern as u32).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_braille() {
+        assert_eq!(braille(0), '\u{2800}');
+        assert_eq!(braille(0x01 | 0x40), '\u{2841}');
+        assert_eq!(braille(0xff), '\u{28ff}');
+    }
+
+    #[test]
+    fn test_cell_pattern() {
+        // a white left column and a transparent pixel in the right one
+        let mut img = image::RgbaImage::from_pixel(3, 5, Rgba([0, 0, 0, 255]));
+        for y in 0..5 {
+            img.put_pixel(0, y, Rgba([255, 255, 255, 255]));
+        }
+        img.put_pixel(1, 0, Rgba([255, 255, 255, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+        let mut config = Config {
+            transparent: true,
+            ..Default::default()
+        };
+        assert_eq!(cell_pattern(&img, 0, 0, &config), 0x47);
+        // the trailing cells are padded with dots that are not raised
+        assert_eq!(cell_pattern(&img, 0, 1, &config), 0x01);
+        assert_eq!(cell_pattern(&img, 1, 0, &config), 0);
+
+        config.braille_threshold = 0;
+        assert_eq!(cell_pattern(&img, 0, 0, &config), 0xf7);
+    }
+
+    #[test]
+    fn test_write_rows() {
+        // a white left column, then a black cell and a transparent one, which shows the light
+        // squares of the checkerboard unless transparent is set
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(6, 4, |x, _| match x {
+            0 => Rgba([255, 255, 255, 255]),
+            1..=3 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        }));
+        let mut config = Config {
+            resize: false,
+            absolute_offset: false,
+            truecolor: Some(true),
+            braille_color: Some((255, 0, 0)),
+            ..Default::default()
+        };
+        let mut buffer = termcolor::Buffer::ansi();
+        let size = write_rows(&mut buffer, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(size, (3, 1));
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "\x1b[0G\x1b[0m\x1b[38;2;255;0;0m\u{2847}\u{2800}\u{286a}\x1b[0m\n"
+        );
+
+        // the terminal's colors are kept, and empty cells are skipped
+        config.braille_color = None;
+        config.transparent = true;
+        let mut buffer = termcolor::Buffer::ansi();
+        write_rows(&mut buffer, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "\x1b[0G\x1b[0m\u{2847}\x1b[1C\x1b[1C\x1b[0m\n"
+        );
+    }
+}
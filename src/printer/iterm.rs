@@ -1,20 +1,20 @@
 use crate::error::ViuResult;
-use crate::printer::{adjust_offset, find_best_fit, Printer};
+use crate::printer::{adjust_offset, best_fit, Printer};
 use crate::Config;
 use image::{DynamicImage, GenericImageView};
-use lazy_static::lazy_static;
 use std::io::{BufReader, Read, Write};
+use std::sync::OnceLock;
 
 #[allow(non_camel_case_types)]
 pub struct iTermPrinter {}
 
-lazy_static! {
-    static ref ITERM_SUPPORT: bool = check_iterm_support();
-}
+static ITERM_SUPPORT: OnceLock<bool> = OnceLock::new();
 
 /// Returns the terminal's support for the iTerm graphics protocol.
+///
+/// The support is only checked on the first call, later ones return the same result.
 pub fn is_iterm_supported() -> bool {
-    *ITERM_SUPPORT
+    *ITERM_SUPPORT.get_or_init(check_iterm_support)
 }
 
 impl Printer for iTermPrinter {
@@ -23,7 +23,7 @@ impl Printer for iTermPrinter {
 
         // Transform the dynamic image to a PNG which can be given directly to iTerm
         let mut png_bytes: Vec<u8> = Vec::new();
-        let _ = image::codecs::png::PngEncoder::new(&mut png_bytes).encode(
+        image::codecs::png::PngEncoder::new(&mut png_bytes).encode(
             img.as_bytes(),
             width,
             height,
@@ -53,7 +53,7 @@ fn print_buffer(img: &DynamicImage, img_content: &[u8], config: &Config) -> ViuR
 
     adjust_offset(&mut stdout, config)?;
 
-    let (w, h) = find_best_fit(&img, config.width, config.height);
+    let (w, h) = best_fit(img, config);
 
     writeln!(
         stdout,
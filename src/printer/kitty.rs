@@ -1,26 +1,35 @@
 use crate::error::{ViuError, ViuResult};
-use crate::printer::{adjust_offset, find_best_fit, Printer};
+use crate::printer::{adjust_offset, best_fit, Printer};
+use crate::utils::{query_terminal, DEFAULT_QUERY_TIMEOUT};
 use crate::Config;
-use console::{Key, Term};
+use console::Key;
 use image::GenericImageView;
-use lazy_static::lazy_static;
+use std::io::Error;
 use std::io::Write;
-use std::io::{Error, ErrorKind};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 pub struct KittyPrinter {}
 
-lazy_static! {
-    static ref KITTY_SUPPORT: KittySupport = check_kitty_support();
-}
+static KITTY_SUPPORT: OnceLock<KittySupport> = OnceLock::new();
 
 /// Returns the terminal's support for the Kitty graphics protocol.
+///
+/// The support is only checked on the first call, later ones return the same result. This
+/// includes printing, so the `query_timeout` of the first Kitty print is the one that counts.
 pub fn get_kitty_support() -> KittySupport {
-    *KITTY_SUPPORT
+    kitty_support(DEFAULT_QUERY_TIMEOUT)
+}
+
+// The support is only checked once, so `timeout` is ignored after the first call. Kitty has
+// `timeout` to answer whether it can read from a file, after which remote support is assumed.
+pub(crate) fn kitty_support(timeout: Duration) -> KittySupport {
+    *KITTY_SUPPORT.get_or_init(|| check_kitty_support(timeout))
 }
 
 impl Printer for KittyPrinter {
     fn print(&self, img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-        match get_kitty_support() {
+        match kitty_support(config.query_timeout) {
             KittySupport::None => Err(ViuError::KittyNotSupported),
             KittySupport::Local => {
                 // print from file
@@ -33,6 +42,14 @@ impl Printer for KittyPrinter {
         }
     }
 
+    fn clear(&self, _config: &Config) -> ViuResult {
+        let mut stdout = std::io::stdout();
+        // a=d without further keys deletes all images visible on the screen
+        write!(stdout, "\x1b_Ga=d\x1b\\")?;
+        stdout.flush()?;
+        Ok(())
+    }
+
     // TODO: guess_format() here in order to treat PNGs specially (f=100).
     // Also, maybe get channel count and use f=24 or f=32 accordingly.
     // fn print_from_file(&self, filename: &str, config: &Config) -> ViuResult<(u32, u32)> {}
@@ -50,10 +67,10 @@ pub enum KittySupport {
 }
 
 // Check if Kitty protocol can be used
-fn check_kitty_support() -> KittySupport {
+fn check_kitty_support(timeout: Duration) -> KittySupport {
     if let Ok(term) = std::env::var("TERM") {
         if term.contains("kitty") {
-            if has_local_support().is_ok() {
+            if has_local_support(timeout).is_ok() {
                 return KittySupport::Local;
             } else {
                 return KittySupport::Remote;
@@ -64,49 +81,44 @@ fn check_kitty_support() -> KittySupport {
 }
 
 // Query the terminal whether it can display an image from a file
-fn has_local_support() -> ViuResult {
+fn has_local_support(timeout: Duration) -> ViuResult {
     // create a temp file that will hold a 1x1 image
     let x = image::RgbaImage::new(1, 1);
     let raw_img = x.as_raw();
     let path = store_in_tmp_file(raw_img)?;
 
     // send the query
-    print!(
+    let query = format!(
         // t=t tells Kitty it's reading from a temp file and will delete if afterwards
         "\x1b_Gi=31,s=1,v=1,a=q,t=t;{}\x1b\\",
-        base64::encode(path.to_str().ok_or_else(|| std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Could not convert path to &str"
-        ))?)
+        base64::encode(
+            path.to_str()
+                .ok_or_else(|| std::io::Error::other("Could not convert path to &str"))?
+        )
     );
-    std::io::stdout().flush()?;
-
-    // collect Kitty's response after the query
-    let term = Term::stdout();
-    let mut response = Vec::new();
-
-    // TODO: could use a queue of length 3
-    while let Ok(key) = term.read_key() {
-        // the response will end with Esc('x1b'), followed by Backslash('\')
-        let should_break = key == Key::UnknownEscSeq(vec!['\\']);
-        response.push(key);
-        if should_break {
-            break;
-        }
-    }
 
-    // Kitty response should end with these 3 Keys if it was successful
-    let expected = [
-        Key::Char('O'),
-        Key::Char('K'),
-        Key::UnknownEscSeq(vec!['\\']),
-    ];
+    // collect Kitty's response after the query.
+    // the response will end with Esc('x1b'), followed by Backslash('\')
+    let response = query_terminal(&query, timeout, |r| r.ends_with(b"\x1b\\"))?;
 
-    if response.len() >= expected.len() && response[response.len() - 3..] == expected {
-        return Ok(());
+    // Kitty response should be OK if it was successful
+    match parse_kitty_response(&response) {
+        Some(b"OK") => Ok(()),
+        _ => Err(ViuError::KittyResponse(
+            String::from_utf8_lossy(&response)
+                .chars()
+                .map(Key::Char)
+                .collect(),
+        )),
     }
+}
 
-    Err(ViuError::KittyResponse(response))
+// Parse an answer such as ESC _ G i=31 ; OK ESC \ into its message, the part after the keys
+fn parse_kitty_response(response: &[u8]) -> Option<&[u8]> {
+    let start = response.windows(3).position(|w| w == b"\x1b_G")? + 3;
+    let response = response[start..].strip_suffix(b"\x1b\\")?;
+    let message = response.iter().position(|&b| b == b';')? + 1;
+    Some(&response[message..])
 }
 
 // Print with kitty graphics protocol through a temp file
@@ -120,19 +132,20 @@ fn print_local(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u3
     adjust_offset(&mut stdout, config)?;
 
     // get the desired width and height
-    let (w, h) = find_best_fit(&img, config.width, config.height);
+    let (w, h) = best_fit(img, config);
 
     write!(
         stdout,
-        "\x1b_Gf=32,s={},v={},c={},r={},a=T,t=t;{}\x1b\\",
+        "\x1b_Gf=32,s={},v={},c={},r={},a=T,t=t{};{}\x1b\\",
         img.width(),
         img.height(),
         w,
         h,
-        base64::encode(path.to_str().ok_or_else(|| ViuError::IO(Error::new(
-            ErrorKind::Other,
-            "Could not convert path to &str"
-        )))?)
+        z_index_key(config),
+        base64::encode(
+            path.to_str()
+                .ok_or_else(|| ViuError::IO(Error::other("Could not convert path to &str")))?
+        )
     )?;
     writeln!(stdout)?;
     stdout.flush()?;
@@ -141,28 +154,48 @@ fn print_local(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u3
 }
 
 // Print with escape codes
-// TODO: try compression
 fn print_remote(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+    let mut stdout = std::io::stdout();
+    adjust_offset(&mut stdout, config)?;
+
+    let (w, h) = write_remote(&mut stdout, img, config)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok((w, h))
+}
+
+// Return the escape codes that transmit and display the image, without moving the cursor
+pub(crate) fn kitty_bytes(img: &image::DynamicImage, config: &Config) -> ViuResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_remote(&mut bytes, img, config)?;
+    Ok(bytes)
+}
+
+// Write the image as escape codes, split in chunks of 4096 bytes of data
+// TODO: try compression
+fn write_remote(
+    out: &mut impl Write,
+    img: &image::DynamicImage,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
     let rgba = img.to_rgba8();
     let raw = rgba.as_raw();
     let encoded = base64::encode(raw);
     let mut iter = encoded.chars().peekable();
 
-    let mut stdout = std::io::stdout();
-    adjust_offset(&mut stdout, config)?;
-
-    let (w, h) = find_best_fit(&img, config.width, config.height);
+    let (w, h) = best_fit(img, config);
 
     let first_chunk: String = iter.by_ref().take(4096).collect();
 
     // write the first chunk, which describes the image
     write!(
-        stdout,
-        "\x1b_Gf=32,a=T,t=d,s={},v={},c={},r={},m=1;{}\x1b\\",
+        out,
+        "\x1b_Gf=32,a=T,t=d,s={},v={},c={},r={}{},m=1;{}\x1b\\",
         img.width(),
         img.height(),
         w,
         h,
+        z_index_key(config),
         first_chunk
     )?;
 
@@ -170,13 +203,20 @@ fn print_remote(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u
     while iter.peek().is_some() {
         let chunk: String = iter.by_ref().take(4096).collect();
         let m = if iter.peek().is_some() { 1 } else { 0 };
-        write!(stdout, "\x1b_Gm={};{}\x1b\\", m, chunk)?;
+        write!(out, "\x1b_Gm={};{}\x1b\\", m, chunk)?;
     }
-    writeln!(stdout)?;
-    stdout.flush()?;
     Ok((w, h))
 }
 
+// Placement key that layers the image below or above the text, if requested.
+// Negative values draw the image behind the text.
+fn z_index_key(config: &Config) -> String {
+    match config.z_index {
+        Some(z) => format!(",z={}", z),
+        None => String::new(),
+    }
+}
+
 // Create a file in temporary dir and write the byte slice to it.
 fn store_in_tmp_file(buf: &[u8]) -> std::result::Result<std::path::PathBuf, ViuError> {
     let (mut tmpfile, path) = tempfile::Builder::new()
@@ -191,3 +231,48 @@ fn store_in_tmp_file(buf: &[u8]) -> std::result::Result<std::path::PathBuf, ViuE
     tmpfile.flush()?;
     Ok(path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kitty_response() {
+        assert_eq!(
+            parse_kitty_response(b"\x1b_Gi=31;OK\x1b\\"),
+            Some(&b"OK"[..])
+        );
+        assert_eq!(
+            parse_kitty_response(b"noise\x1b_Gi=31;ENOENT:file not found\x1b\\"),
+            Some(&b"ENOENT:file not found"[..])
+        );
+        // not a graphics response, or a cut off one
+        assert_eq!(parse_kitty_response(b"\x1b[12;1R"), None);
+        assert_eq!(parse_kitty_response(b"\x1b_Gi=31;OK"), None);
+        assert_eq!(parse_kitty_response(b"\x1b_Gi=31\x1b\\"), None);
+    }
+
+    #[test]
+    fn test_kitty_bytes() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::new(40, 40));
+        let config = Config {
+            width: Some(20),
+            height: Some(10),
+            ..Default::default()
+        };
+        let bytes = String::from_utf8(kitty_bytes(&img, &config).unwrap()).unwrap();
+        assert!(bytes.starts_with("\x1b_Gf=32,a=T,t=d,s=40,v=40,c=20,r=10,m=1;"));
+        // 6400 bytes of pixels need more than one chunk
+        assert!(bytes.ends_with("\x1b\\"));
+        assert_eq!(bytes.matches("\x1b_G").count(), 3);
+        assert!(bytes.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn test_z_index_key() {
+        let mut config = Config::default();
+        assert_eq!(z_index_key(&config), "");
+        config.z_index = Some(-1);
+        assert_eq!(z_index_key(&config), ",z=-1");
+    }
+}
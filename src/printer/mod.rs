@@ -1,22 +1,40 @@
-use crate::config::Config;
+use crate::config::{Config, PartialRowPolicy};
+use crate::edges::overlay_edges;
 use crate::error::{ViuError, ViuResult};
-use crate::utils::terminal_size;
-use crossterm::cursor::{MoveRight, MoveTo, MoveToPreviousLine};
+use crate::utils::{cursor_row, detect_truecolor, terminal_size, terminal_size_or};
+use crossterm::cursor::{MoveRight, MoveTo, MoveToColumn, MoveToPreviousLine};
 use crossterm::execute;
+use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
 use std::io::Write;
+use termcolor::{BufferWriter, ColorChoice};
 
 mod block;
-pub use block::BlockPrinter;
+pub(crate) use block::{print_buffer, write_in_batches};
+pub use block::{print_to, render, BlockPrinter};
 
 mod kitty;
+use kitty::kitty_bytes;
+pub(crate) use kitty::kitty_support;
 pub use kitty::{get_kitty_support, KittyPrinter, KittySupport};
 
 mod sixel;
 pub use self::sixel::get_sixel_support;
+use self::sixel::sixel_bytes;
+pub(crate) use self::sixel::sixel_support;
 pub use self::sixel::SixelPrinter;
 pub use self::sixel::SixelSupport;
 
+mod sextant;
+pub use sextant::SextantPrinter;
+
+mod braille;
+pub use braille::BraillePrinter;
+
+mod ascii;
+pub use ascii::AsciiPrinter;
+
 mod iterm;
 pub use iterm::iTermPrinter;
 pub use iterm::is_iterm_supported;
@@ -31,13 +49,57 @@ pub trait Printer {
             .decode()?;
         self.print(&img, config)
     }
+    // Remove an image that was printed with the same config from the terminal.
+    fn clear(&self, config: &Config) -> ViuResult {
+        let mut stdout = std::io::stdout();
+        clear_area(&mut stdout, config)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Graphics protocols whose output can be obtained with [render_to_bytes].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsBackend {
+    /// Kitty graphics protocol, with the image data sent in the escape codes.
+    Kitty,
+    /// Sixel graphics.
+    Sixel,
+}
+
+/// Render an image with a graphics protocol and return the bytes a terminal should receive,
+/// instead of writing them to stdout. Useful to record the output or send it elsewhere.
+///
+/// The bytes are the same ones the matching printer would write, without any cursor movement
+/// for the offsets. The Kitty sequence always contains the image data, since a temporary file
+/// is of no use once the bytes leave the machine.
+/// ## Example
+/// ```no_run
+/// use viuer::{render_to_bytes, Config, GraphicsBackend};
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let bytes = render_to_bytes(&img, &Config::default(), GraphicsBackend::Kitty)
+///     .expect("Image rendering failed.");
+/// std::fs::write("img.kitty", bytes).expect("Could not write file.");
+/// ```
+pub fn render_to_bytes(
+    img: &DynamicImage,
+    config: &Config,
+    backend: GraphicsBackend,
+) -> ViuResult<Vec<u8>> {
+    match backend {
+        GraphicsBackend::Kitty => kitty_bytes(img, config),
+        GraphicsBackend::Sixel => sixel_bytes(img),
+    }
 }
 
 /// Resize a [image::DynamicImage] so that it fits within optional width and height bounds.
 /// If none are provided, terminal size is used instead.
 pub fn resize(img: &DynamicImage, width: Option<u32>, height: Option<u32>) -> DynamicImage {
-    let (w, h) = find_best_fit(img, width, height);
+    resize_to_cells(img, find_best_fit(img, width, height))
+}
 
+// Resize the image to cover the given terminal cells
+pub(crate) fn resize_to_cells(img: &DynamicImage, (w, h): (u32, u32)) -> DynamicImage {
     // find_best_fit returns values in terminal cells. Hence, we multiply by two
     // because a 5x10 image can fit in 5x5 cells.
     img.resize_exact(w, 2 * h, image::imageops::FilterType::Triangle)
@@ -62,30 +124,150 @@ pub fn resize(img: &DynamicImage, width: Option<u32>, height: Option<u32>) -> Dy
 /// assert_eq!(w, 80);
 /// assert_eq!(h, 20);
 //TODO: it might make more sense to change signiture from img to (width, height)
-fn find_best_fit(img: &DynamicImage, width: Option<u32>, height: Option<u32>) -> (u32, u32) {
-    let (img_width, img_height) = img.dimensions();
+pub(crate) fn find_best_fit(
+    img: &DynamicImage,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> (u32, u32) {
+    best_fit_in(img.dimensions(), width, height, terminal_size())
+}
 
+/// Find the size in pixels that an image of `img_width`x`img_height` pixels is resized to
+/// before it is printed with half blocks, so that it fits the terminal, or the `width` and
+/// `height` from the config, while keeping its aspect ratio. Every terminal cell holds two
+/// pixels, one above the other, so the returned height is twice the number of rows.
+///
+/// If neither `width` nor `height` is set, the terminal size is used, or the config's
+/// `fallback_terminal_size` if it cannot be found. If only one of them is set, the other
+/// follows from the aspect ratio, and if both are set, they are used as they are.
+/// ## Example
+/// ```
+/// use viuer::{fit_dimensions, Config};
+/// let config = Config {
+///     width: Some(40),
+///     ..Default::default()
+/// };
+/// // 40 columns and 10 rows
+/// assert_eq!(fit_dimensions(160, 80, &config), (40, 20));
+/// ```
+pub fn fit_dimensions(img_width: u32, img_height: u32, config: &Config) -> (u32, u32) {
+    let (w, h) = best_fit_in(
+        (img_width, img_height),
+        config.width,
+        config.height,
+        terminal_size_or(config.fallback_terminal_size),
+    );
+    (w, 2 * h)
+}
+
+// Same as find_best_fit, with the bounds from the config. Falls back to the config's terminal
+// size if the real one is not known.
+pub(crate) fn best_fit(img: &DynamicImage, config: &Config) -> (u32, u32) {
+    let (w, h) = fit_dimensions(img.width(), img.height(), config);
+    (w, h / 2)
+}
+
+fn best_fit_in(
+    (img_width, img_height): (u32, u32),
+    width: Option<u32>,
+    height: Option<u32>,
+    (term_w, term_h): (u16, u16),
+) -> (u32, u32) {
     // Match user's width and height preferences
     match (width, height) {
         (None, None) => {
-            let (term_w, term_h) = terminal_size();
-            let (w, h) = fit_dimensions(img_width, img_height, term_w as u32, term_h as u32);
+            let (w, h) = fit_in_cells(img_width, img_height, term_w as u32, term_h as u32);
 
             // One less row because two reasons:
             // - the prompt after executing the command will take a line
             // - gifs flicker
-            let h = if h == term_h as u32 { h - 1 } else { h };
+            let h = if h == term_h as u32 && h > 1 {
+                h - 1
+            } else {
+                h
+            };
             (w, h)
         }
         // Either width or height is specified, will fit and preserve aspect ratio.
-        (Some(w), None) => fit_dimensions(img_width, img_height, w, img_height),
-        (None, Some(h)) => fit_dimensions(img_width, img_height, img_width, h),
+        (Some(w), None) => fit_in_cells(img_width, img_height, w, img_height),
+        (None, Some(h)) => fit_in_cells(img_width, img_height, img_width, h),
 
         // Both width and height are specified, will resize to match exactly
         (Some(w), Some(h)) => (w, h),
     }
 }
 
+// Writer to stdout for the printers that need colors to show the image. Fails if colors are
+// disabled, rather than printing a blank image.
+pub(crate) fn color_stdout(config: &Config) -> ViuResult<BufferWriter> {
+    match config.resolved_color_choice() {
+        ColorChoice::Never => Err(ViuError::InvalidConfiguration(
+            "colors are disabled, set color_choice to print them anyway".to_owned(),
+        )),
+        choice => Ok(BufferWriter::stdout(choice)),
+    }
+}
+
+// The config with truecolor support detected if it is left to auto-detection, so that the
+// environment is read once per print instead of once per pixel
+pub(crate) fn resolve_truecolor(config: &Config) -> Cow<'_, Config> {
+    match config.truecolor {
+        Some(_) => Cow::Borrowed(config),
+        None => Cow::Owned(Config {
+            truecolor: Some(detect_truecolor()),
+            ..config.clone()
+        }),
+    }
+}
+
+// Get the image ready for a printer drawing `px_w`x`px_h` pixels in every terminal cell:
+// resize it to fit the constraints, if any, draw the edges and apply the partial row policy
+pub(crate) fn prepare_image<'a>(
+    img: &'a DynamicImage,
+    config: &Config,
+    (px_w, px_h): (u32, u32),
+) -> Cow<'a, DynamicImage> {
+    let mut img = Cow::Borrowed(img);
+    if config.resize {
+        let (w, h) = best_fit(&img, config);
+        img = Cow::Owned(img.resize_exact(px_w * w, px_h * h, FilterType::Triangle));
+    }
+
+    // edges are found after resizing, so that they stay sharp
+    if let Some(style) = &config.edge_overlay {
+        img = Cow::Owned(overlay_edges(&img, style));
+    }
+
+    if let Some(fitted) = fit_partial_rows(&img, px_h, config.partial_row_policy) {
+        img = Cow::Owned(fitted);
+    }
+    img
+}
+
+// Apply the partial row policy to an image printed with `px_per_row` pixel rows in every
+// terminal row. Returns None if the image can be printed as it is.
+pub(crate) fn fit_partial_rows(
+    img: &DynamicImage,
+    px_per_row: u32,
+    policy: PartialRowPolicy,
+) -> Option<DynamicImage> {
+    let (width, height) = img.dimensions();
+    let partial = height % px_per_row;
+    if partial == 0 {
+        return None;
+    }
+
+    match policy {
+        PartialRowPolicy::Pad => None,
+        // an image smaller than a row is kept, rather than printing nothing
+        PartialRowPolicy::Truncate if height < px_per_row => None,
+        PartialRowPolicy::Truncate => Some(img.crop_imm(0, 0, width, height - partial)),
+        PartialRowPolicy::Upscale => {
+            Some(img.resize_exact(width, height - partial + px_per_row, FilterType::Nearest))
+        }
+    }
+}
+
 /// Given width & height of an image, scale the size so that it can fit within given bounds
 /// while preserving aspect ratio. Will only scale down - if dimensions are smaller than the
 /// bounds, they will be returned unmodified.
@@ -97,8 +279,8 @@ fn find_best_fit(img: &DynamicImage, width: Option<u32>, height: Option<u32>) ->
 /// ratio of 1:1, would be to use all of the available height, 15, which is
 /// equivalent in size to 30 vertical cells. Hence, the returned dimensions will be 30x15.
 ///
-/// assert_eq!((30, 15), viuer::fit_dimensions(100, 100, 40, 15));
-fn fit_dimensions(width: u32, height: u32, bound_width: u32, bound_height: u32) -> (u32, u32) {
+/// assert_eq!((30, 15), fit_in_cells(100, 100, 40, 15));
+fn fit_in_cells(width: u32, height: u32, bound_width: u32, bound_height: u32) -> (u32, u32) {
     let bound_height = 2 * bound_height;
 
     if width <= bound_width && height <= bound_height {
@@ -125,10 +307,25 @@ fn fit_dimensions(width: u32, height: u32, bound_width: u32, bound_height: u32)
 // Move the cursor to a location from where it should start printing. Calculations are based on
 // offsets from the config.
 fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
+    move_to_first_line(stdout, config, true)?;
+    // MoveRight(0) would still move one column in most terminals
+    if config.x > 0 {
+        execute!(stdout, MoveRight(config.x))?;
+    }
+    Ok(())
+}
+
+// Move the cursor to the first column of the line the image starts on. All printers follow the
+// same rule, including when y is 0:
+// - with absolute_offset, the image starts on row y of the screen
+// - otherwise, it starts y lines below the cursor's line, or -y lines above it if y is negative.
+//   A y of 0 keeps the cursor's line. The move up is clamped only if `query_cursor` allows
+//   asking the terminal where the cursor is.
+fn move_to_first_line(stdout: &mut impl Write, config: &Config, query_cursor: bool) -> ViuResult {
     if config.absolute_offset {
         if config.y >= 0 {
-            // If absolute_offset, move to (x,y).
-            execute!(stdout, MoveTo(config.x, config.y as u16))?;
+            // If absolute_offset, move to (0,y).
+            execute!(stdout, MoveTo(0, config.y as u16))?;
         } else {
             //Negative values do not make sense.
             return Err(ViuError::InvalidConfiguration(
@@ -137,8 +334,12 @@ fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
         }
     } else if config.y < 0 {
         // MoveUp if negative
-        execute!(stdout, MoveToPreviousLine(-config.y as u16))?;
-        execute!(stdout, MoveRight(config.x))?;
+        let lines = if query_cursor {
+            lines_up(config)?
+        } else {
+            config.y.unsigned_abs()
+        };
+        execute!(stdout, MoveToPreviousLine(lines))?;
     } else {
         // Move down y lines
         for _ in 0..config.y {
@@ -146,11 +347,70 @@ fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
             // observed when config.y > 0 and cursor is on the last terminal line
             writeln!(stdout)?;
         }
-        execute!(stdout, MoveRight(config.x))?;
+        // the cursor might be in the middle of a line, or stay in place after a
+        // newline when the terminal is in raw mode
+        execute!(stdout, MoveToColumn(0))?;
     }
     Ok(())
 }
 
+// Column the rows of an image `cols` wide start at: config.x, or the margin that centers the
+// image if config.center is set. Images wider than the terminal start at column 0.
+pub(crate) fn left_margin(cols: u32, config: &Config) -> u16 {
+    if config.center {
+        let (term_w, _) = terminal_size_or(config.fallback_terminal_size);
+        (term_w as u32).saturating_sub(cols) as u16 / 2
+    } else {
+        config.x
+    }
+}
+
+// Overwrite the cells covered by an image with spaces. When width or height are not specified
+// in the config, the terminal size is used instead.
+fn clear_area(stdout: &mut impl Write, config: &Config) -> ViuResult {
+    let (term_w, term_h) = terminal_size_or(config.fallback_terminal_size);
+    let width = config.width.unwrap_or(term_w as u32);
+    let height = config.height.unwrap_or(term_h as u32);
+
+    adjust_offset(stdout, config)?;
+    let blank = " ".repeat(width as usize);
+    for row in 0..height {
+        // writeln! leaves the cursor at the first column, so the x offset has to be reapplied
+        if row > 0 && config.x > 0 {
+            execute!(stdout, MoveRight(config.x))?;
+        }
+        writeln!(stdout, "{}", blank)?;
+    }
+    Ok(())
+}
+
+// How many lines to move up for a negative, relative y offset. Moving above the top of the
+// screen behaves differently between terminals, so the move is either clamped to the top row or
+// rejected, depending on config.clamp_offsets. If the cursor position cannot be queried, the
+// full offset is used.
+fn lines_up(config: &Config) -> ViuResult<u16> {
+    let lines = config.y.unsigned_abs();
+    let row = match cursor_row(config.query_timeout) {
+        Ok(row) => row,
+        Err(_) => return Ok(lines),
+    };
+    check_lines_up(lines, row, config.clamp_offsets)
+}
+
+fn check_lines_up(lines: u16, row: u16, clamp: bool) -> ViuResult<u16> {
+    if lines <= row {
+        Ok(lines)
+    } else if clamp {
+        Ok(row)
+    } else {
+        Err(ViuError::InvalidConfiguration(format!(
+            "y offset {} moves above the top of the screen, cursor is on row {}",
+            -(lines as i32),
+            row
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -321,24 +581,149 @@ mod tests {
     }
 
     #[test]
-    fn test_fit_dimensions() {
+    fn test_clear_area() {
+        let config = Config {
+            absolute_offset: false,
+            width: Some(3),
+            height: Some(2),
+            ..Default::default()
+        };
+        let mut out: Vec<u8> = Vec::new();
+        clear_area(&mut out, &config).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[0G   \n   \n");
+    }
+
+    #[test]
+    fn test_adjust_offset() {
+        let adjusted = |config: &Config| {
+            let mut out: Vec<u8> = Vec::new();
+            adjust_offset(&mut out, config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let mut config = Config {
+            absolute_offset: false,
+            ..Default::default()
+        };
+        // y == 0 starts at the beginning of the cursor's line, without moving right
+        assert_eq!(adjusted(&config), "\x1b[0G");
+        config.y = 2;
+        config.x = 3;
+        assert_eq!(adjusted(&config), "\n\n\x1b[0G\x1b[3C");
+
+        config.absolute_offset = true;
+        config.x = 0;
+        config.y = 0;
+        assert_eq!(adjusted(&config), "\x1b[1;1H");
+        config.y = -1;
+        let mut out: Vec<u8> = Vec::new();
+        assert!(adjust_offset(&mut out, &config).is_err());
+    }
+
+    #[test]
+    fn test_check_lines_up() {
+        assert_eq!(check_lines_up(3, 10, false).unwrap(), 3);
+        assert_eq!(check_lines_up(10, 10, false).unwrap(), 10);
+        assert_eq!(check_lines_up(12, 10, true).unwrap(), 10);
+        assert!(check_lines_up(12, 10, false).is_err());
+    }
+
+    #[test]
+    fn test_fit_in_cells() {
         // ratio 1:1
-        assert_eq!((40, 20), fit_dimensions(100, 100, 40, 50));
-        assert_eq!((20, 10), fit_dimensions(100, 100, 40, 10));
+        assert_eq!((40, 20), fit_in_cells(100, 100, 40, 50));
+        assert_eq!((20, 10), fit_in_cells(100, 100, 40, 10));
         // ratio 3:2
-        assert_eq!((30, 10), fit_dimensions(240, 160, 30, 100));
+        assert_eq!((30, 10), fit_in_cells(240, 160, 30, 100));
         // ratio 5:7
-        assert_eq!((200, 140), fit_dimensions(300, 420, 320, 140));
+        assert_eq!((200, 140), fit_in_cells(300, 420, 320, 140));
     }
 
     #[test]
     fn test_fit_smaller_than_bounds() {
-        assert_eq!((4, 1), fit_dimensions(4, 3, 80, 24));
-        assert_eq!((4, 1), fit_dimensions(4, 1, 80, 24));
+        assert_eq!((4, 1), fit_in_cells(4, 3, 80, 24));
+        assert_eq!((4, 1), fit_in_cells(4, 1, 80, 24));
     }
 
     #[test]
     fn test_fit_equal_to_bounds() {
-        assert_eq!((80, 12), fit_dimensions(80, 24, 80, 24));
+        assert_eq!((80, 12), fit_in_cells(80, 24, 80, 24));
+    }
+
+    #[test]
+    fn test_best_fit_in_tiny_terminal() {
+        assert_eq!(best_fit_in((160, 80), None, None, (80, 24)), (80, 20));
+        assert_eq!(best_fit_in((160, 80), None, None, (1, 1)), (1, 1));
+    }
+
+    #[test]
+    fn test_color_stdout() {
+        let mut config = Config {
+            color_choice: Some(ColorChoice::Never),
+            ..Default::default()
+        };
+        assert!(matches!(
+            color_stdout(&config),
+            Err(ViuError::InvalidConfiguration(_))
+        ));
+        config.color_choice = None;
+        assert!(color_stdout(&config).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_truecolor() {
+        let config = Config {
+            truecolor: Some(false),
+            ..Default::default()
+        };
+        assert!(matches!(resolve_truecolor(&config), Cow::Borrowed(_)));
+        let config = Config::default();
+        let resolved = resolve_truecolor(&config);
+        assert!(matches!(resolved, Cow::Owned(_)));
+        assert!(resolved.truecolor.is_some());
+    }
+
+    #[test]
+    fn test_fit_dimensions() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(300, 420));
+        let mut config = Config::default();
+        // the terminal is 80x24 in tests, minus a row for the prompt
+        assert_eq!(fit_dimensions(300, 420, &config), (34, 46));
+        assert_eq!(best_fit(&img, &config), (34, 23));
+
+        config.height = Some(10);
+        assert_eq!(fit_dimensions(300, 420, &config), (14, 20));
+        config.width = Some(50);
+        assert_eq!(fit_dimensions(300, 420, &config), (50, 20));
+        assert_eq!(best_fit(&img, &config), (50, 10));
+    }
+
+    #[test]
+    fn test_left_margin() {
+        let mut config = Config {
+            x: 3,
+            ..Default::default()
+        };
+        assert_eq!(left_margin(20, &config), 3);
+        config.center = true;
+        // the test terminal is 80 columns wide
+        assert_eq!(left_margin(20, &config), 30);
+        assert_eq!(left_margin(21, &config), 29);
+        assert_eq!(left_margin(100, &config), 0);
+    }
+
+    #[test]
+    fn test_fit_partial_rows() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(4, 5));
+        assert!(fit_partial_rows(&img, 2, PartialRowPolicy::Pad).is_none());
+
+        let truncated = fit_partial_rows(&img, 2, PartialRowPolicy::Truncate).unwrap();
+        assert_eq!(truncated.dimensions(), (4, 4));
+        let upscaled = fit_partial_rows(&img, 3, PartialRowPolicy::Upscale).unwrap();
+        assert_eq!(upscaled.dimensions(), (4, 6));
+
+        let even = DynamicImage::ImageRgba8(image::RgbaImage::new(4, 6));
+        assert!(fit_partial_rows(&even, 2, PartialRowPolicy::Upscale).is_none());
+        let tiny = DynamicImage::ImageRgba8(image::RgbaImage::new(4, 1));
+        assert!(fit_partial_rows(&tiny, 2, PartialRowPolicy::Truncate).is_none());
     }
 }
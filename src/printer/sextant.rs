@@ -0,0 +1,254 @@
+use crate::error::ViuResult;
+use crate::printer::block::{pixel_rgb, print_buffer, quantize, Ansi256Cache};
+use crate::printer::Printer;
+use crate::Config;
+
+use crossterm::cursor::MoveRight;
+use crossterm::execute;
+use image::{DynamicImage, GenericImageView};
+use termcolor::{ColorSpec, WriteColor};
+
+const FULL_BLOCK: char = '\u{2588}';
+const LEFT_HALF_BLOCK: char = '\u{258c}';
+const RIGHT_HALF_BLOCK: char = '\u{2590}';
+// First of the sextant characters, from Symbols for Legacy Computing
+const FIRST_SEXTANT: u32 = 0x1fb00;
+
+// Subpixel patterns that already exist as half blocks, missing from the sextant characters
+const LEFT_COLUMN: u8 = 0b01_0101;
+const RIGHT_COLUMN: u8 = 0b10_1010;
+
+type Rgb = (u8, u8, u8);
+
+pub struct SextantPrinter {}
+
+impl Printer for SextantPrinter {
+    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+        let stdout = super::color_stdout(config)?;
+        let mut out_buffer = stdout.buffer();
+        write_rows(&mut out_buffer, img, config, true, |out_buffer| {
+            print_buffer(&stdout, out_buffer)
+        })
+    }
+}
+
+// Write the image to out_buffer, calling `row_done` after every terminal row. The terminal
+// is only queried for the cursor position if `query_cursor` is set.
+fn write_rows<W: WriteColor>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    query_cursor: bool,
+    mut row_done: impl FnMut(&mut W) -> ViuResult,
+) -> ViuResult<(u32, u32)> {
+    let resolved = super::resolve_truecolor(config);
+    let config: &Config = &resolved;
+
+    super::move_to_first_line(out_buffer, config, query_cursor)?;
+
+    // a cell holds 2x3 pixels
+    let prepared = super::prepare_image(img, config, (2, 3));
+    let img: &DynamicImage = &prepared;
+    let (width, height) = img.dimensions();
+    let (cols, rows) = (width.div_ceil(2), height.div_ceil(3));
+    let x = super::left_margin(cols, config);
+    let mut cache = Ansi256Cache::new();
+
+    for row in 0..rows {
+        if x > 0 {
+            execute!(out_buffer, MoveRight(x))?;
+        }
+        for col in 0..cols {
+            let cell = cell_pixels(img, col, row, config);
+            write_cell(out_buffer, &cell, config, &mut cache)?;
+        }
+        out_buffer.reset()?;
+        writeln!(out_buffer)?;
+        row_done(out_buffer)?;
+    }
+
+    Ok((cols, rows))
+}
+
+// Colors of the 2x3 pixels covered by a cell, row by row. Pixels past the edges of the image
+// are transparent.
+fn cell_pixels(img: &DynamicImage, col: u32, row: u32, config: &Config) -> [Option<Rgb>; 6] {
+    let (width, height) = img.dimensions();
+    let mut cell = [None; 6];
+    for (i, color) in cell.iter_mut().enumerate() {
+        let x = 2 * col + i as u32 % 2;
+        let y = 3 * row + i as u32 / 2;
+        if x < width && y < height {
+            *color = pixel_rgb((x, y, img.get_pixel(x, y)), (width, height), config);
+        }
+    }
+    cell
+}
+
+fn write_cell(
+    out: &mut impl WriteColor,
+    cell: &[Option<Rgb>; 6],
+    config: &Config,
+    cache: &mut Ansi256Cache,
+) -> ViuResult {
+    let (pattern, fg, bg) = split_cell(cell);
+    let fg = match fg {
+        Some(fg) => fg,
+        None => {
+            // nothing to draw, let the background show through
+            out.reset()?;
+            execute!(out, MoveRight(1))?;
+            return Ok(());
+        }
+    };
+
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(quantize(fg, config, cache)));
+    color.set_bg(bg.map(|bg| quantize(bg, config, cache)));
+    // the color spec only sets colors, so reset the ones of the previous cell first
+    out.reset()?;
+    out.set_color(&color)?;
+    write!(out, "{}", sextant(pattern))?;
+    Ok(())
+}
+
+// Split the pixels of a cell into two groups of similar colors. Returns the pattern of the
+// pixels drawn in the foreground, one bit per pixel starting with the top left one, along with
+// the average colors of both groups. Transparent pixels always go to the background, which has
+// no color if all of them are transparent.
+fn split_cell(cell: &[Option<Rgb>; 6]) -> (u8, Option<Rgb>, Option<Rgb>) {
+    let opaque: Vec<(usize, Rgb)> = cell
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.map(|c| (i, c)))
+        .collect();
+
+    if opaque.len() < cell.len() {
+        let pattern = opaque.iter().fold(0, |p, (i, _)| p | 1 << i);
+        let colors: Vec<_> = opaque.iter().map(|(_, c)| *c).collect();
+        return (pattern, average(&colors), None);
+    }
+
+    // the two most different pixels are the seeds of the groups
+    let mut seeds = (cell[0].unwrap(), cell[0].unwrap());
+    let mut widest = 0;
+    for (i, &(_, a)) in opaque.iter().enumerate() {
+        for &(_, b) in opaque.iter().skip(i + 1) {
+            let d = distance(a, b);
+            if d > widest {
+                widest = d;
+                seeds = (a, b);
+            }
+        }
+    }
+
+    let mut pattern = 0;
+    let (mut fg, mut bg) = (Vec::new(), Vec::new());
+    for &(i, c) in opaque.iter() {
+        if distance(c, seeds.0) <= distance(c, seeds.1) {
+            pattern |= 1 << i;
+            fg.push(c);
+        } else {
+            bg.push(c);
+        }
+    }
+    (pattern, average(&fg), average(&bg))
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn average(colors: &[Rgb]) -> Option<Rgb> {
+    if colors.is_empty() {
+        return None;
+    }
+    let n = colors.len() as u32;
+    let avg =
+        |f: fn(&Rgb) -> u8| ((colors.iter().map(|c| f(c) as u32).sum::<u32>() + n / 2) / n) as u8;
+    Some((avg(|c| c.0), avg(|c| c.1), avg(|c| c.2)))
+}
+
+// Character showing the pixels set in the 6 bit pattern in the foreground color
+fn sextant(pattern: u8) -> char {
+    match pattern {
+        0 => ' ',
+        LEFT_COLUMN => LEFT_HALF_BLOCK,
+        RIGHT_COLUMN => RIGHT_HALF_BLOCK,
+        0b11_1111 => FULL_BLOCK,
+        p => {
+            // the sextants are ordered by pattern, skipping the two half blocks
+            let skipped = (p > LEFT_COLUMN) as u32 + (p > RIGHT_COLUMN) as u32;
+            std::char::from_u32(FIRST_SEXTANT + p as u32 - 1 - skipped).unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_sextant() {
+        assert_eq!(sextant(0b00_0001), '\u{1fb00}');
+        assert_eq!(sextant(0b01_0100), '\u{1fb13}');
+        assert_eq!(sextant(0b01_0110), '\u{1fb14}');
+        assert_eq!(sextant(0b11_1110), '\u{1fb3b}');
+        assert_eq!(sextant(LEFT_COLUMN), LEFT_HALF_BLOCK);
+        assert_eq!(sextant(0b11_1111), FULL_BLOCK);
+    }
+
+    #[test]
+    fn test_split_cell() {
+        let (red, blue) = (Some((250, 0, 0)), Some((0, 0, 250)));
+        // top row red, the rest blue
+        let (pattern, fg, bg) = split_cell(&[red, red, blue, blue, blue, blue]);
+        assert_eq!(pattern, 0b00_0011);
+        assert_eq!((fg, bg), (red, blue));
+
+        let (pattern, fg, bg) = split_cell(&[red; 6]);
+        assert_eq!((pattern, fg, bg), (0b11_1111, red, None));
+
+        // transparent pixels are left out
+        let (pattern, fg, bg) = split_cell(&[None, red, None, blue, None, None]);
+        assert_eq!((pattern, fg, bg), (0b00_1010, Some((125, 0, 125)), None));
+
+        assert_eq!(split_cell(&[None; 6]), (0, None, None));
+    }
+
+    #[test]
+    fn test_write_rows() {
+        // a cell with a red top row and one with a red left column, both over blue, followed
+        // by a partial cell at the right edge
+        let (red, blue) = (Rgba([250, 0, 0, 255]), Rgba([0, 0, 250, 255]));
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(5, 3, |x, y| {
+            if (x < 2 && y == 0) || x == 2 || x == 4 {
+                red
+            } else {
+                blue
+            }
+        }));
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            truecolor: Some(true),
+            ..Default::default()
+        };
+        let mut buffer = termcolor::Buffer::ansi();
+        let size = write_rows(&mut buffer, &img, &config, false, |_| Ok(())).unwrap();
+        assert_eq!(size, (3, 1));
+
+        // every cell resets the colors of the previous one before setting its own
+        let cell = |colors: &str, glyph: char| format!("\x1b[0m\x1b[0m{}{}", colors, glyph);
+        let red_on_blue = "\x1b[38;2;250;0;0m\x1b[48;2;0;0;250m";
+        let expected = format!(
+            "\x1b[0G{}{}{}\x1b[0m\n",
+            cell(red_on_blue, '\u{1fb02}'),
+            cell(red_on_blue, LEFT_HALF_BLOCK),
+            cell("\x1b[38;2;250;0;0m", LEFT_HALF_BLOCK)
+        );
+        assert_eq!(String::from_utf8(buffer.into_inner()).unwrap(), expected);
+    }
+}
@@ -1,11 +1,14 @@
 use crate::error::{ViuError, ViuResult};
 use crate::printer::Printer;
+use crate::utils::{query_terminal, DEFAULT_QUERY_TIMEOUT};
 use crate::Config;
 use image::DynamicImage;
 use image::GenericImageView;
-use lazy_static::lazy_static;
 use std::env;
-use std::io::{Read, Write};
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 pub struct SixelPrinter {}
 
@@ -16,6 +19,35 @@ impl Printer for SixelPrinter {
 }
 
 fn print_sixel(img: &image::DynamicImage) -> ViuResult<(u32, u32)> {
+    let (x_pixles, y_pixels) = img.dimensions();
+
+    encode_sixel(img, None)?;
+
+    // No end of line printed by encoder
+    let mut stdout = std::io::stdout();
+    stdout.flush()?;
+
+    let y_pixel_size = get_pixel_size();
+    let small_y_pixels = y_pixels as u16;
+    Ok((
+        x_pixles,
+        match small_y_pixels.checked_div(y_pixel_size) {
+            Some(rows) => (rows + 1) as u32,
+            None => 5000,
+        },
+    ))
+}
+
+// Return the sixel data of the image. The encoder can only write to files, so it goes
+// through a temporary one.
+pub(crate) fn sixel_bytes(img: &image::DynamicImage) -> ViuResult<Vec<u8>> {
+    let tmpfile = tempfile::Builder::new().prefix(".tmp.viuer.").tempfile()?;
+    encode_sixel(img, Some(tmpfile.path()))?;
+    Ok(std::fs::read(tmpfile.path())?)
+}
+
+// Encode the image into sixel data, written to stdout or to the file at `output`
+fn encode_sixel(img: &image::DynamicImage, output: Option<&Path>) -> ViuResult {
     use sixel::encoder::{Encoder, QuickFrameBuilder};
     use sixel::optflags::EncodePolicy;
 
@@ -25,6 +57,9 @@ fn print_sixel(img: &image::DynamicImage) -> ViuResult<(u32, u32)> {
     let raw = rgba.as_raw();
 
     let encoder = Encoder::new()?;
+    if let Some(path) = output {
+        encoder.set_output(path)?;
+    }
 
     encoder.set_encode_policy(EncodePolicy::Fast)?;
 
@@ -35,21 +70,7 @@ fn print_sixel(img: &image::DynamicImage) -> ViuResult<(u32, u32)> {
         .pixels(raw.to_vec());
 
     encoder.encode_bytes(frame)?;
-
-    // No end of line printed by encoder
-    let mut stdout = std::io::stdout();
-    stdout.flush()?;
-
-    let y_pixel_size = get_pixel_size();
-    let small_y_pixels = y_pixels as u16;
-    Ok((
-        x_pixles,
-        if y_pixel_size <= 0 {
-            5000
-        } else {
-            (small_y_pixels / y_pixel_size + 1) as u32
-        },
-    ))
+    Ok(())
 }
 
 #[cfg(windows)]
@@ -84,7 +105,7 @@ fn get_pixel_size() -> u16 {
             return 0;
         }
     }
-    if size_out.ws_ypixel <= 0 || size_out.ws_row <= 0 {
+    if size_out.ws_ypixel == 0 || size_out.ws_row == 0 {
         return 0;
     }
     size_out.ws_ypixel / size_out.ws_row
@@ -96,13 +117,20 @@ impl std::convert::From<sixel::status::Error> for crate::error::ViuError {
     }
 }
 
-lazy_static! {
-    static ref SIXEL_SUPPORT: SixelSupport = check_sixel_support();
-}
+static SIXEL_SUPPORT: OnceLock<SixelSupport> = OnceLock::new();
 
-/// Returns the terminal's support for the Kitty graphics protocol.
+/// Returns the terminal's support for the Sixel graphics protocol.
+///
+/// The support is only checked on the first call, later ones return the same result. This
+/// includes printing, so the `query_timeout` of the first Sixel print is the one that counts.
 pub fn get_sixel_support() -> SixelSupport {
-    *SIXEL_SUPPORT
+    sixel_support(DEFAULT_QUERY_TIMEOUT)
+}
+
+// The support is only checked once, so `timeout` is ignored after the first call. The
+// terminal has `timeout` to answer the query, after which Sixel is assumed to not be supported.
+pub(crate) fn sixel_support(timeout: Duration) -> SixelSupport {
+    *SIXEL_SUPPORT.get_or_init(|| check_sixel_support(timeout))
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -113,11 +141,6 @@ pub enum SixelSupport {
     /// The Sixel graphics protocol is supported.
     Supported,
 }
-///TODO check for sixel support on windows
-#[cfg(windows)]
-fn xterm_check_sixel_support() -> Result<SixelSupport, std::io::Error> {
-    SixelSupport::None
-}
 
 // Parsing the escape code sequence
 // see
@@ -141,37 +164,13 @@ enum XTERMSupportParserState {
     FoundFour,
 }
 
-#[cfg(unix)]
-fn xterm_check_sixel_support() -> Result<SixelSupport, std::io::Error> {
-    use std::fs::write;
-    use std::io::stdin;
-    use termios::*;
-    //STDOUT_FILENO
-    let file_descriptor = 1;
-    let mut term_info = Termios::from_fd(file_descriptor)?;
-    let old_iflag = term_info.c_iflag;
-    let old_lflag = term_info.c_lflag;
-    //setup the terminal so that it will send the device attributes
-    //to stdin rather than writing them to the screen
-    term_info.c_iflag &= !(ISTRIP);
-    term_info.c_iflag &= !(INLCR);
-    term_info.c_iflag &= !(ICRNL);
-    term_info.c_iflag &= !(IGNCR);
-    term_info.c_iflag &= !(IXOFF);
-
-    term_info.c_lflag &= !(ECHO);
-    term_info.c_lflag &= !(ICANON);
-
-    tcsetattr(file_descriptor, TCSANOW, &mut term_info)?;
-
+fn xterm_check_sixel_support(timeout: Duration) -> Result<SixelSupport, std::io::Error> {
     //Send Device Attributes
     // see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Functions-using-CSI-_-ordered-by-the-final-character_s_
-    write("/dev/tty", "\x1b[0c")?;
-    let mut std_in_buffer: [u8; 256] = [0; 256];
-    let size_read = stdin().read(&mut std_in_buffer)?;
+    // the response ends with c
+    let response = query_terminal("\x1b[0c", timeout, |r| r.last() == Some(&b'c'))?;
     let mut state = XTERMSupportParserState::ExpectCSIESC;
-    for i in 0..size_read {
-        let current_char = std_in_buffer[i];
+    for &current_char in response.iter() {
         use XTERMSupportParserState::{
             ExpectCSIESC, ExpectCSIOpenBracket, ExpectQuestionMark, FoundFour, InvalidState,
             ParseParameter, ParseParameterMightBeFour, ParseParameterNotFour,
@@ -231,10 +230,6 @@ fn xterm_check_sixel_support() -> Result<SixelSupport, std::io::Error> {
             FoundFour => break,
         }
     }
-    term_info.c_iflag = old_iflag;
-    term_info.c_lflag = old_lflag;
-    tcsetattr(file_descriptor, TCSANOW, &mut term_info)?;
-
     Ok(if let XTERMSupportParserState::FoundFour = state {
         SixelSupport::Supported
     } else {
@@ -243,15 +238,15 @@ fn xterm_check_sixel_support() -> Result<SixelSupport, std::io::Error> {
 }
 
 // // Check if Sixel protocol can be used
-fn check_sixel_support() -> SixelSupport {
+fn check_sixel_support(timeout: Duration) -> SixelSupport {
     use SixelSupport::{None, Supported};
 
     match env::var("TERM").unwrap_or(String::from("None")).as_str() {
         "mlterm" => Supported,
         "yaft-256color" => Supported,
-        "st-256color" => xterm_check_sixel_support().unwrap_or(None),
-        "xterm" => xterm_check_sixel_support().unwrap_or(None),
-        "xterm-256color" => xterm_check_sixel_support().unwrap_or(None),
+        "st-256color" => xterm_check_sixel_support(timeout).unwrap_or(None),
+        "xterm" => xterm_check_sixel_support(timeout).unwrap_or(None),
+        "xterm-256color" => xterm_check_sixel_support(timeout).unwrap_or(None),
         _ => match env::var("TERM_PROGRAM")
             .unwrap_or(String::from("None"))
             .as_str()
@@ -267,9 +262,21 @@ fn check_sixel_support() -> SixelSupport {
 ///sixel support
 #[test]
 #[ignore]
-fn sixel_support() {
-    match check_sixel_support() {
+fn test_sixel_support() {
+    match check_sixel_support(DEFAULT_QUERY_TIMEOUT) {
         SixelSupport::Supported => (),
-        SixelSupport::None => assert!(false),
+        SixelSupport::None => panic!("sixel is not supported"),
     }
 }
+
+#[test]
+fn test_sixel_bytes() {
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        4,
+        6,
+        image::Rgba([255, 0, 0, 255]),
+    ));
+    let bytes = sixel_bytes(&img).unwrap();
+    assert!(bytes.starts_with(b"\x1bP"));
+    assert!(bytes.ends_with(b"\x1b\\"));
+}
@@ -0,0 +1,141 @@
+use crate::config::Config;
+use crate::error::ViuResult;
+use crate::printer::{color_stdout, print_buffer, write_in_batches};
+use crossterm::cursor::{RestorePosition, SavePosition};
+use crossterm::event::{self, Event};
+use crossterm::execute;
+use image::DynamicImage;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use termcolor::WriteColor;
+
+/// Reveal an image one terminal row at a time, waiting `row_delay` after every row, for a
+/// retro scanline effect.
+///
+/// The image is always printed with half blocks, since graphics protocols draw it at once.
+/// Pressing a key skips the remaining delays and prints the rest of the image
+/// right away. Unless the terminal is in raw mode, it only sees the key once Enter is pressed.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_scanline, Config};
+/// use std::time::Duration;
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// print_scanline(&img, Duration::from_millis(30), &Config::default())
+///     .expect("Image printing failed.");
+/// ```
+pub fn print_scanline(
+    img: &DynamicImage,
+    row_delay: Duration,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    let mut stdout = std::io::stdout();
+    if config.restores_cursor() {
+        execute!(stdout, SavePosition)?;
+    }
+
+    let result = color_stdout(config).and_then(|stdout| {
+        let mut out_buffer = stdout.buffer();
+        reveal(
+            &mut out_buffer,
+            img,
+            config,
+            |out_buffer| print_buffer(&stdout, out_buffer),
+            || wait_for_key(row_delay),
+        )
+    });
+
+    if config.restores_cursor() {
+        execute!(stdout, RestorePosition)?;
+    }
+    result
+}
+
+// Write the image to out_buffer, flushing it and calling `wait` after every terminal row. Once
+// `wait` returns true, the remaining rows are written without waiting.
+fn reveal<W: WriteColor>(
+    out_buffer: &mut W,
+    img: &DynamicImage,
+    config: &Config,
+    flush: impl FnMut(&mut W) -> ViuResult,
+    mut wait: impl FnMut() -> bool,
+) -> ViuResult<(u32, u32)> {
+    let mut skipped = false;
+    write_in_batches(out_buffer, img, config, flush, || {
+        if !skipped {
+            skipped = wait();
+        }
+        Ok(())
+    })
+}
+
+// Wait for `delay` to pass. Returns true, as soon as it happens, if a key is pressed meanwhile.
+fn wait_for_key(delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match event::poll(left) {
+            Ok(true) => {
+                if let Ok(Event::Key(_)) = event::read() {
+                    return true;
+                }
+            }
+            Ok(false) => return false,
+            // no terminal to read events from, so just wait
+            Err(_) => {
+                std::thread::sleep(left);
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reveal() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 8, |_, y| {
+            image::Rgba([30 * y as u8, 0, 0, 255])
+        }));
+        let config = Config {
+            resize: false,
+            absolute_offset: false,
+            truecolor: Some(true),
+            ..Default::default()
+        };
+        let mut rows = Vec::new();
+        let mut waits = 0;
+        let size = reveal(
+            &mut termcolor::Buffer::ansi(),
+            &img,
+            &config,
+            |buffer| {
+                if !buffer.is_empty() {
+                    rows.push(String::from_utf8(buffer.as_slice().to_vec()).unwrap());
+                }
+                buffer.clear();
+                Ok(())
+            },
+            || {
+                waits += 1;
+                // a key is pressed during the second delay
+                waits == 2
+            },
+        )
+        .unwrap();
+        assert_eq!(size, (4, 4));
+        assert_eq!(waits, 2);
+
+        // every row is written out on its own, followed by the final reset
+        assert_eq!(rows.pop().as_deref(), Some("\x1b[0m"));
+        assert_eq!(rows.len(), 4);
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(row.matches('\n').count(), 1);
+            // lower half blocks, drawn over the top pixel's color
+            let top = format!("\x1b[48;2;{};0;0m", 60 * y);
+            let bottom = format!("\x1b[38;2;{};0;0m", 60 * y + 30);
+            assert!(row.contains(&top) && row.contains(&bottom), "{:?}", row);
+        }
+    }
+}
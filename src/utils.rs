@@ -1,18 +1,53 @@
+use crossterm::tty::IsTty;
 use std::env;
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use termcolor::ColorChoice;
 
-const DEFAULT_TERM_SIZE: (u16, u16) = (80, 24);
+pub const DEFAULT_TERM_SIZE: (u16, u16) = (80, 24);
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
 
-pub fn truecolor_available() -> bool {
-    if let Ok(value) = env::var("COLORTERM") {
-        value.contains("truecolor") || value.contains("24bit")
-    } else {
-        false
+// TERM values of terminals known to support truecolor without setting COLORTERM
+const TRUECOLOR_TERMS: [&str; 5] = ["kitty", "alacritty", "wezterm", "ghostty", "foot"];
+
+/// Check whether the terminal supports 24-bit color.
+///
+/// `COLORTERM` is looked at first, which should be `truecolor` or `24bit`. If it does not
+/// say so, `TERM` is checked for a `-direct` terminfo entry or a terminal known to support
+/// truecolor. This is what [Config](crate::Config)'s `truecolor: None` uses on every print.
+/// ## Example
+/// ```
+/// use viuer::{detect_truecolor, Config};
+///
+/// let conf = Config {
+///     truecolor: Some(detect_truecolor()),
+///     ..Default::default()
+/// };
+/// ```
+pub fn detect_truecolor() -> bool {
+    truecolor_for(
+        &env::var("COLORTERM").unwrap_or_default(),
+        &env::var("TERM").unwrap_or_default(),
+    )
+}
+
+// Whether the given COLORTERM and TERM values mean that 24-bit color is supported
+fn truecolor_for(colorterm: &str, term: &str) -> bool {
+    if colorterm.contains("truecolor") || colorterm.contains("24bit") {
+        return true;
     }
+
+    term.ends_with("-direct")
+        || term.contains("truecolor")
+        || term.contains("24bit")
+        || TRUECOLOR_TERMS.iter().any(|t| term.contains(t))
 }
 
 /// Try to get the terminal size. If unsuccessful, fallback to a default (80x24).
 ///
-/// Uses [crossterm::terminal::size].
+/// Uses [crossterm::terminal::size]. A reported width or height of 0, which happens in some
+/// headless environments, counts as unsuccessful.
 /// ## Example
 /// The example below prints "img.jpg" with dimensions 80x40 in the center of the terminal.
 /// ```no_run
@@ -32,29 +67,323 @@ pub fn truecolor_available() -> bool {
 /// };
 /// print_from_file("img.jpg", &config).expect("Image printing failed.");
 /// ```
-#[cfg(not(test))]
 pub fn terminal_size() -> (u16, u16) {
-    match crossterm::terminal::size() {
-        Ok(s) => s,
-        Err(_) => DEFAULT_TERM_SIZE,
-    }
+    terminal_size_or(DEFAULT_TERM_SIZE)
+}
+
+// Get the terminal size, or `fallback` if it is not known
+#[cfg(not(test))]
+pub fn terminal_size_or(fallback: (u16, u16)) -> (u16, u16) {
+    checked_size(crossterm::terminal::size().ok(), fallback)
 }
 
 // Return a constant when running the tests
 #[cfg(test)]
-pub fn terminal_size() -> (u16, u16) {
+pub fn terminal_size_or(_fallback: (u16, u16)) -> (u16, u16) {
     DEFAULT_TERM_SIZE
 }
 
+// Use the fallback if the size is missing or empty. The fallback itself is at least 1x1.
+fn checked_size(size: Option<(u16, u16)>, fallback: (u16, u16)) -> (u16, u16) {
+    match size {
+        Some((w, h)) if w > 0 && h > 0 => (w, h),
+        _ => (std::cmp::max(1, fallback.0), std::cmp::max(1, fallback.1)),
+    }
+}
+
+// Whether stdout is a terminal, rather than a file or a pipe
+pub(crate) fn is_tty() -> bool {
+    std::io::stdout().is_tty()
+}
+
+// Colors allowed by the environment
+#[cfg(not(test))]
+pub(crate) fn env_color_choice() -> ColorChoice {
+    color_choice_for(env::var_os("NO_COLOR"), is_tty())
+}
+
+// Always print colors when running the tests, whose output is not a terminal
+#[cfg(test)]
+pub(crate) fn env_color_choice() -> ColorChoice {
+    ColorChoice::Always
+}
+
+// No colors if NO_COLOR is set to anything but an empty string, or if the output does not go
+// to a terminal
+fn color_choice_for(no_color: Option<OsString>, tty: bool) -> ColorChoice {
+    if no_color.is_some_and(|value| !value.is_empty()) || !tty {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Always
+    }
+}
+
+// Send an escape sequence query to the terminal and collect its response. Both go through
+// /dev/tty, so that the query works while stdin is a pipe or another terminal. Reading stops
+// once `is_complete` accepts the bytes received so far. If the terminal does not answer within
+// `timeout`, an error of kind TimedOut is returned instead.
+#[cfg(unix)]
+pub fn query_terminal(
+    query: &str,
+    timeout: Duration,
+    is_complete: impl Fn(&[u8]) -> bool,
+) -> std::io::Result<Vec<u8>> {
+    use std::os::unix::io::AsRawFd;
+    use termios::*;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    let file_descriptor = tty.as_raw_fd();
+    let mut term_info = Termios::from_fd(file_descriptor)?;
+    let old_term_info = term_info;
+    // setup the terminal so that the response can be read right away
+    // rather than being written to the screen
+    term_info.c_iflag &= !(ISTRIP | INLCR | ICRNL | IGNCR | IXOFF);
+    term_info.c_lflag &= !(ECHO | ICANON);
+    tcsetattr(file_descriptor, TCSANOW, &term_info)?;
+
+    let response = read_response(&mut tty, query, timeout, is_complete);
+
+    // restore the terminal even if reading failed
+    tcsetattr(file_descriptor, TCSANOW, &old_term_info)?;
+    response
+}
+
+#[cfg(unix)]
+fn read_response(
+    tty: &mut std::fs::File,
+    query: &str,
+    timeout: Duration,
+    is_complete: impl Fn(&[u8]) -> bool,
+) -> std::io::Result<Vec<u8>> {
+    use std::io::{Error, ErrorKind, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    tty.write_all(query.as_bytes())?;
+    tty.flush()?;
+
+    let file_descriptor = tty.as_raw_fd();
+
+    let deadline = Instant::now() + timeout;
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 256];
+    while !is_complete(&response) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut poll_fd = libc::pollfd {
+            fd: file_descriptor,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // the terminal is polled and read directly, because a buffered reader could keep
+        // bytes that poll does not know about
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as libc::c_int) };
+        if ready < 0 {
+            return Err(Error::last_os_error());
+        }
+        if ready == 0 {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                "terminal did not respond to query",
+            ));
+        }
+        let size_read = unsafe {
+            libc::read(
+                file_descriptor,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if size_read < 0 {
+            return Err(Error::last_os_error());
+        }
+        if size_read == 0 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "terminal closed before responding to query",
+            ));
+        }
+        response.extend_from_slice(&buffer[..size_read as usize]);
+    }
+    Ok(response)
+}
+
+// Set once a cursor query times out. A terminal that does not answer it will not answer any
+// later ones either, so they fail right away instead of waiting for the timeout again
+static CURSOR_QUERY_UNANSWERED: AtomicBool = AtomicBool::new(false);
+
+// Query the row the cursor is on, counting from 0 at the top of the screen.
+pub fn cursor_row(timeout: Duration) -> std::io::Result<u16> {
+    use std::io::{Error, ErrorKind};
+
+    unless_unanswered(&CURSOR_QUERY_UNANSWERED, || {
+        // Device Status Report, answered with ESC [ row ; column R
+        let response = query_terminal("\x1b[6n", timeout, |r| r.last() == Some(&b'R'))?;
+        parse_cursor_row(&response)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid cursor position response"))
+    })
+}
+
+// Run the query, unless an earlier one timed out according to `unanswered`
+fn unless_unanswered<T>(
+    unanswered: &AtomicBool,
+    query: impl FnOnce() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    use std::io::{Error, ErrorKind};
+
+    if unanswered.load(Ordering::Relaxed) {
+        return Err(Error::new(
+            ErrorKind::TimedOut,
+            "terminal did not respond to an earlier query",
+        ));
+    }
+    let result = query();
+    if matches!(&result, Err(e) if e.kind() == ErrorKind::TimedOut) {
+        unanswered.store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+fn parse_cursor_row(response: &[u8]) -> Option<u16> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.rfind("\x1b[")? + 2;
+    let end = start + response[start..].find(';')?;
+    let row: u16 = response[start..end].parse().ok()?;
+    // rows reported by the terminal start from 1
+    row.checked_sub(1)
+}
+
+/// Query the terminal for its foreground color through OSC 10.
+///
+/// Returns None if the terminal does not answer within `timeout`, which can be taken from
+/// `query_timeout` in the [Config](crate::Config), or if the answer cannot be understood.
+/// ## Example
+/// ```no_run
+/// use viuer::{detect_foreground_color, Config};
+///
+/// let config = Config::default();
+/// if let Some((r, g, b)) = detect_foreground_color(config.query_timeout) {
+///     println!("Text is drawn in rgb({}, {}, {})", r, g, b);
+/// }
+/// ```
+pub fn detect_foreground_color(timeout: Duration) -> Option<(u8, u8, u8)> {
+    // the answer is terminated the same way as the query, here with BEL
+    let response = query_terminal("\x1b]10;?\x07", timeout, |r| {
+        r.last() == Some(&b'\x07') || r.ends_with(b"\x1b\\")
+    })
+    .ok()?;
+    parse_osc_color(&response)
+}
+
+// Parse an answer such as ESC ] 10 ; rgb:ffff/8080/0000 BEL. Every channel has 1 to 4 hex digits.
+fn parse_osc_color(response: &[u8]) -> Option<(u8, u8, u8)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("rgb:")? + 4;
+    let end = response[start..]
+        .find(['\x07', '\x1b'])
+        .map_or(response.len(), |i| start + i);
+
+    let mut channels = response[start..end].split('/').map(|hex| {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    match channels.next() {
+        None => Some(rgb),
+        Some(_) => None,
+    }
+}
+
+///TODO query the terminal on windows
+#[cfg(windows)]
+pub fn query_terminal(
+    _query: &str,
+    _timeout: Duration,
+    _is_complete: impl Fn(&[u8]) -> bool,
+) -> std::io::Result<Vec<u8>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "terminal queries are not supported on windows",
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_truecolor() {
-        env::set_var("COLORTERM", "truecolor");
-        assert!(truecolor_available());
-        env::set_var("COLORTERM", "");
-        assert!(!truecolor_available());
+    fn test_truecolor_for() {
+        assert!(truecolor_for("truecolor", "xterm-256color"));
+        assert!(truecolor_for("24bit", ""));
+        assert!(!truecolor_for("", "xterm-256color"));
+        assert!(!truecolor_for("", ""));
+        assert!(truecolor_for("", "xterm-direct"));
+        assert!(truecolor_for("", "xterm-kitty"));
+    }
+
+    #[test]
+    fn test_color_choice_for() {
+        assert_eq!(color_choice_for(None, true), ColorChoice::Always);
+        assert_eq!(color_choice_for(Some("".into()), true), ColorChoice::Always);
+        assert_eq!(color_choice_for(Some("1".into()), true), ColorChoice::Never);
+        assert_eq!(color_choice_for(None, false), ColorChoice::Never);
+    }
+
+    #[test]
+    fn test_unless_unanswered() {
+        use std::io::{Error, ErrorKind};
+
+        let unanswered = AtomicBool::new(false);
+        assert_eq!(unless_unanswered(&unanswered, || Ok(3)).unwrap(), 3);
+        let invalid = unless_unanswered::<u16>(&unanswered, || Err(ErrorKind::InvalidData.into()));
+        assert_eq!(invalid.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(!unanswered.load(Ordering::Relaxed));
+
+        let timed_out = unless_unanswered::<u16>(&unanswered, || {
+            Err(Error::new(ErrorKind::TimedOut, "no answer"))
+        });
+        assert_eq!(timed_out.unwrap_err().kind(), ErrorKind::TimedOut);
+        // later queries are not run
+        let skipped = unless_unanswered(&unanswered, || -> std::io::Result<u16> {
+            panic!("queried again")
+        });
+        assert_eq!(skipped.unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_checked_size() {
+        assert_eq!(checked_size(Some((120, 40)), (80, 24)), (120, 40));
+        assert_eq!(checked_size(Some((0, 0)), (80, 24)), (80, 24));
+        assert_eq!(checked_size(Some((120, 0)), (100, 30)), (100, 30));
+        assert_eq!(checked_size(None, (80, 24)), (80, 24));
+        assert_eq!(checked_size(None, (0, 0)), (1, 1));
+    }
+
+    #[test]
+    fn test_parse_osc_color() {
+        assert_eq!(
+            parse_osc_color(b"\x1b]10;rgb:ffff/8080/0000\x07"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(
+            parse_osc_color(b"\x1b]10;rgb:f/0/8\x1b\\"),
+            Some((255, 0, 136))
+        );
+        assert_eq!(parse_osc_color(b"\x1b]10;rgb:ff/00\x07"), None);
+        assert_eq!(parse_osc_color(b"\x1b]10;?\x07"), None);
+    }
+
+    #[test]
+    fn test_parse_cursor_row() {
+        assert_eq!(parse_cursor_row(b"\x1b[12;40R"), Some(11));
+        assert_eq!(parse_cursor_row(b"\x1b[1;1R"), Some(0));
+        assert_eq!(parse_cursor_row(b"\x1b[;1R"), None);
+        assert_eq!(parse_cursor_row(b"garbage"), None);
     }
 }
@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::error::ViuResult;
+use crate::utils::terminal_size_or;
+use image::{DynamicImage, GenericImageView};
+
+/// Print the part of an image that is visible through a viewport whose top left corner is at
+/// `origin`, given in image pixels. The image is not resized, so every pixel takes half a cell.
+///
+/// The viewport is `width`x`height` terminal cells, as set in the [Config]. If they are not
+/// provided, the terminal size is used instead. The origin is clamped so that the viewport
+/// never goes past the edges of the image.
+///
+/// Redrawing in place requires the image to be printed at the same position every time, for
+/// example by using `absolute_offset` (the default).
+/// ## Example
+/// ```no_run
+/// use viuer::{print_viewport, Config};
+/// let img = image::open("map.png").expect("Could not open map.");
+/// let conf = Config {
+///     width: Some(40),
+///     height: Some(20),
+///     ..Default::default()
+/// };
+/// // show the area starting 100 pixels from the left and 50 from the top
+/// print_viewport(&img, (100, 50), &conf).expect("Image printing failed.");
+/// ```
+pub fn print_viewport(
+    img: &DynamicImage,
+    origin: (u32, u32),
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    let (view_w, view_h) = viewport_size(config);
+    let (x, y) = clamp_origin(img.dimensions(), origin, (view_w, view_h));
+
+    let cropped = img.crop_imm(x, y, view_w.min(img.width()), view_h.min(img.height()));
+    // keep the checkerboard behind transparent areas still while panning
+    let (origin_x, origin_y) = config.checkerboard_origin;
+    let config = Config {
+        resize: false,
+        checkerboard_origin: (origin_x + x, origin_y + y),
+        ..config.clone()
+    };
+    crate::print(&cropped, &config)
+}
+
+/// Move the viewport `page_scroll_step` cells to the left and redraw it.
+/// See [print_viewport] for details.
+pub fn pan_left(
+    img: &DynamicImage,
+    origin: &mut (u32, u32),
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    origin.0 = origin.0.saturating_sub(config.page_scroll_step);
+    pan(img, origin, config)
+}
+
+/// Move the viewport `page_scroll_step` cells to the right and redraw it.
+/// See [print_viewport] for details.
+pub fn pan_right(
+    img: &DynamicImage,
+    origin: &mut (u32, u32),
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    origin.0 = origin.0.saturating_add(config.page_scroll_step);
+    pan(img, origin, config)
+}
+
+/// Move the viewport `page_scroll_step` cells up and redraw it.
+/// See [print_viewport] for details.
+pub fn pan_up(
+    img: &DynamicImage,
+    origin: &mut (u32, u32),
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    // a cell is two pixels tall
+    origin.1 = origin.1.saturating_sub(2 * config.page_scroll_step);
+    pan(img, origin, config)
+}
+
+/// Move the viewport `page_scroll_step` cells down and redraw it.
+/// See [print_viewport] for details.
+pub fn pan_down(
+    img: &DynamicImage,
+    origin: &mut (u32, u32),
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    origin.1 = origin.1.saturating_add(2 * config.page_scroll_step);
+    pan(img, origin, config)
+}
+
+// Clamp the moved origin, so that the caller keeps track of where the viewport really is
+fn pan(img: &DynamicImage, origin: &mut (u32, u32), config: &Config) -> ViuResult<(u32, u32)> {
+    *origin = clamp_origin(img.dimensions(), *origin, viewport_size(config));
+    print_viewport(img, *origin, config)
+}
+
+// Size of the viewport in pixels
+fn viewport_size(config: &Config) -> (u32, u32) {
+    let (term_w, term_h) = terminal_size_or(config.fallback_terminal_size);
+    let width = config.width.unwrap_or(term_w as u32);
+    // leave a line for the prompt if the whole terminal is used
+    let height = config
+        .height
+        .unwrap_or_else(|| std::cmp::max(1, term_h as u32 - 1));
+    (width, 2 * height)
+}
+
+// Keep the viewport within the image. If the image is smaller than the viewport, the origin
+// is always 0 along that axis.
+fn clamp_origin(
+    (img_w, img_h): (u32, u32),
+    (x, y): (u32, u32),
+    (view_w, view_h): (u32, u32),
+) -> (u32, u32) {
+    (
+        x.min(img_w.saturating_sub(view_w)),
+        y.min(img_h.saturating_sub(view_h)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_origin() {
+        assert_eq!(clamp_origin((100, 100), (10, 20), (40, 40)), (10, 20));
+        assert_eq!(clamp_origin((100, 100), (90, 70), (40, 40)), (60, 60));
+        // image smaller than the viewport
+        assert_eq!(clamp_origin((20, 10), (5, 5), (40, 40)), (0, 0));
+    }
+
+    #[test]
+    fn test_viewport_size() {
+        let config = Config {
+            width: Some(30),
+            height: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(viewport_size(&config), (30, 20));
+        // terminal size is 80x24 during tests
+        assert_eq!(viewport_size(&Config::default()), (80, 46));
+    }
+
+    #[test]
+    fn test_pan_clamps_at_edges() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(50, 30));
+        let config = Config {
+            width: Some(40),
+            height: Some(10),
+            absolute_offset: false,
+            page_scroll_step: 8,
+            ..Default::default()
+        };
+        let mut origin = (0, 0);
+        pan_right(&img, &mut origin, &config).unwrap();
+        assert_eq!(origin, (8, 0));
+        pan_right(&img, &mut origin, &config).unwrap();
+        assert_eq!(origin, (10, 0));
+        pan_down(&img, &mut origin, &config).unwrap();
+        assert_eq!(origin, (10, 10));
+        pan_left(&img, &mut origin, &config).unwrap();
+        pan_up(&img, &mut origin, &config).unwrap();
+        assert_eq!(origin, (2, 0));
+    }
+}
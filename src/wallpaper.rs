@@ -0,0 +1,128 @@
+use crate::config::Config;
+use crate::error::ViuResult;
+use crate::utils::terminal_size_or;
+use crossterm::cursor::MoveTo;
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use image::DynamicImage;
+use std::io::Write;
+
+// Opacity of the wallpaper over a black background, so that text printed over it stays readable
+const WALLPAPER_BRIGHTNESS: f32 = 0.4;
+
+/// Show an image as a dimmed, full screen background while running `f`, which can print text
+/// over it.
+///
+/// The wallpaper is drawn on the alternate screen, covering the whole terminal and cropped to
+/// its aspect ratio. It is dimmed by fading it towards `transparent_bg`, black by default, with
+/// the `opacity` of the [Config], so only with half blocks. With the Kitty printer, the image is
+/// placed behind the text instead, unless `z_index` is set. When `f` returns, or panics, the
+/// wallpaper is removed and the original screen is restored. The value returned by `f` is
+/// passed through.
+/// ## Example
+/// ```no_run
+/// use viuer::{with_wallpaper, Config};
+/// let img = image::open("wallpaper.jpg").expect("Could not open wallpaper.");
+/// with_wallpaper(&img, &Config::default(), || {
+///     println!("Hello from the foreground!");
+///     std::thread::sleep(std::time::Duration::from_secs(2));
+/// })
+/// .expect("Wallpaper printing failed.");
+/// ```
+pub fn with_wallpaper<R>(
+    img: &DynamicImage,
+    config: &Config,
+    f: impl FnOnce() -> R,
+) -> ViuResult<R> {
+    let config = wallpaper_config(config);
+    execute!(
+        std::io::stdout(),
+        EnterAlternateScreen,
+        Clear(ClearType::All)
+    )?;
+    let mut screen = AlternateScreen {
+        config: &config,
+        active: true,
+    };
+
+    let result = draw_wallpaper(img, &config).and_then(|_| {
+        execute!(std::io::stdout(), MoveTo(0, 0))?;
+        Ok(f())
+    });
+
+    // restore the screen even if drawing failed
+    screen.leave()?;
+    result
+}
+
+// Removes the wallpaper and leaves the alternate screen when dropped, so that the terminal is
+// restored even if `f` panics or an error is returned early
+struct AlternateScreen<'a> {
+    config: &'a Config,
+    active: bool,
+}
+
+impl AlternateScreen<'_> {
+    fn leave(&mut self) -> ViuResult {
+        self.active = false;
+        let cleared = crate::clear_graphics(self.config);
+        execute!(std::io::stdout(), LeaveAlternateScreen)?;
+        cleared
+    }
+}
+
+impl Drop for AlternateScreen<'_> {
+    fn drop(&mut self) {
+        if self.active {
+            let _ = self.leave();
+        }
+    }
+}
+
+// The config the wallpaper is printed and cleared with: at the top left corner, behind the
+// text and dimmed
+fn wallpaper_config(config: &Config) -> Config {
+    Config {
+        absolute_offset: true,
+        x: 0,
+        y: 0,
+        restore_cursor: false,
+        z_index: config.z_index.or(Some(-1)),
+        transparent_bg: config.transparent_bg.or(Some((0, 0, 0))),
+        opacity: config.opacity * WALLPAPER_BRIGHTNESS,
+        ..config.clone()
+    }
+}
+
+fn draw_wallpaper(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+    let (term_w, term_h) = terminal_size_or(config.fallback_terminal_size);
+    // leave out the last line, printing a newline on it would scroll the wallpaper
+    let rows = std::cmp::max(1, term_h as u32 - 1);
+    crate::print_poster(img, term_w as u32, rows, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wallpaper_config() {
+        let config = wallpaper_config(&Config {
+            y: 3,
+            opacity: 0.5,
+            ..Default::default()
+        });
+        assert_eq!((config.x, config.y), (0, 0));
+        assert_eq!(config.z_index, Some(-1));
+        assert_eq!(config.transparent_bg, Some((0, 0, 0)));
+        assert!((config.opacity - 0.2).abs() < 1e-6);
+
+        let config = wallpaper_config(&Config {
+            z_index: Some(2),
+            transparent_bg: Some((0, 0, 40)),
+            ..Default::default()
+        });
+        assert_eq!(config.z_index, Some(2));
+        assert_eq!(config.transparent_bg, Some((0, 0, 40)));
+    }
+}